@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+
+use rocket::{State, delete, get, post, put, serde::json::Json};
+use surrealdb_types::{Datetime, RecordId, ToSql};
+use validator::ValidationError;
+
+use crate::{
+    AppResult, DB,
+    bookmarks::model::{
+        Collection, CollectionRequest, CollectionResponse, CollectionSummary,
+        MAX_COLLECTION_NAME_LEN, SavedPost,
+    },
+    db::{make_cursor, parse_cursor, parse_thing},
+    error::AppError,
+    jwt::AuthUser,
+    posts::{
+        enrich_service,
+        model::{Post, PostResponse},
+        post_service::find_post,
+    },
+};
+
+#[put("/bookmark/<post_id>")]
+pub async fn toggle_bookmark(post_id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let post = find_post(db, parse_thing(post_id)?).await?;
+    enrich_service::ensure_visible(db, &uid, &post).await?;
+    let mut res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $row = (SELECT id FROM bookmarks WHERE in = $uid AND out = $pid LIMIT 1)[0];
+            IF $row = NONE {
+                RELATE $uid->bookmarks->$pid SET collections = [], created_at = time::now();
+            } ELSE {
+                DELETE $row.id;
+            };
+            SELECT VALUE id FROM bookmarks WHERE in = $uid AND out = $pid;
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("uid", uid))
+        .bind(("pid", post.id))
+        .await?;
+    let saved = res.take::<Option<RecordId>>(2)?;
+    if saved.is_some() {
+        Ok("Saved the post".to_string())
+    } else {
+        Ok("Unsaved the post".to_string())
+    }
+}
+
+/// Saved posts, most recently saved first, optionally limited to one
+/// collection. `cursor` is the `cursor` of the last post seen, which pins
+/// down when it was saved. Posts the caller can no longer see (private or
+/// blocked authors, unfinished uploads) are left out.
+#[get("/saved?<collection>&<cursor>&<limit>")]
+pub async fn get_saved(
+    collection: Option<String>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<PostResponse>>> {
+    let uid = parse_thing(&auth.user_id)?;
+    let collection = match collection {
+        Some(id) => Some(find_collection(db, parse_thing(&id)?, &uid).await?.id),
+        None => None,
+    };
+    let limit = limit.unwrap_or(20);
+    let (cursor_at, cursor_id) = cursor.as_deref().map(parse_cursor).transpose()?.unzip();
+    let mut res = db
+        .query(
+            "
+            LET $following = SELECT VALUE following_id FROM follows WHERE follower_id = $uid;
+            LET $blocked = array::union(
+                SELECT VALUE out FROM blocks WHERE in = $uid,
+                SELECT VALUE in FROM blocks WHERE out = $uid
+            );
+            LET $page = SELECT out, created_at FROM bookmarks
+                WHERE in = $uid
+                AND ($collection = NONE OR collections CONTAINS $collection)
+                AND (
+                    $cursor_at = NONE
+                    OR created_at < $cursor_at
+                    OR (created_at = $cursor_at AND out < $cursor_id)
+                )
+                AND (out.status ?? 'ready') = 'ready'
+                AND (out.uid.is_private != true OR out.uid = $uid OR out.uid IN $following)
+                AND out.uid NOT IN $blocked
+                ORDER BY created_at DESC, out DESC
+                LIMIT $limit;
+            SELECT * FROM $page.out;
+            RETURN $page;
+            ",
+        )
+        .bind(("uid", uid.clone()))
+        .bind(("collection", collection))
+        .bind(("cursor_at", cursor_at))
+        .bind(("cursor_id", cursor_id))
+        .bind(("limit", limit))
+        .await?;
+    let posts: Vec<Post> = res.take(3)?;
+    let saved_at: HashMap<String, Datetime> = res
+        .take::<Vec<SavedPost>>(4)?
+        .into_iter()
+        .map(|saved| (saved.out.to_sql(), saved.created_at))
+        .collect();
+    let mut posts = enrich_service::enrich_posts(db, &uid, posts).await?;
+    for post in &mut posts {
+        post.cursor = saved_at
+            .get(&post.id)
+            .map(|created_at| make_cursor(created_at, &post.id));
+    }
+    Ok(Json(posts))
+}
+
+#[post("/collection", data = "<req>")]
+pub async fn create_collection(
+    req: Json<CollectionRequest>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<CollectionResponse>> {
+    let uid = parse_thing(&auth.user_id)?;
+    let name = check_name(&req.name)?;
+    ensure_name_free(db, &uid, &name).await?;
+    let collection = db
+        .query("CREATE ONLY collections SET owner = $uid, name = $name, created_at = time::now()")
+        .bind(("uid", uid))
+        .bind(("name", name))
+        .await?
+        .take::<Option<Collection>>(0)?
+        .ok_or(AppError::XCustomMessage("Failed to create collection"))?;
+    Ok(Json(CollectionResponse {
+        id: collection.id.to_sql(),
+        name: collection.name,
+        posts_count: 0,
+        created_at: collection.created_at,
+    }))
+}
+
+#[put("/rename-collection/<id>", data = "<req>")]
+pub async fn rename_collection(
+    id: &str,
+    req: Json<CollectionRequest>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let collection = find_collection(db, parse_thing(id)?, &uid).await?;
+    let name = check_name(&req.name)?;
+    if name != collection.name {
+        ensure_name_free(db, &uid, &name).await?;
+    }
+    let res = db
+        .query("UPDATE $cid SET name = $name")
+        .bind(("cid", collection.id))
+        .bind(("name", name))
+        .await?;
+    res.check()?;
+    Ok("Collection renamed".to_string())
+}
+
+/// Deletes a collection. Its posts stay saved.
+#[delete("/delete-collection/<id>")]
+pub async fn delete_collection(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let collection = find_collection(db, parse_thing(id)?, &uid).await?;
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            UPDATE bookmarks SET collections -= $cid
+                WHERE in = $uid AND collections CONTAINS $cid;
+            DELETE $cid;
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("uid", uid))
+        .bind(("cid", collection.id))
+        .await?;
+    res.check()?;
+    Ok("Collection deleted".to_string())
+}
+
+#[get("/collections")]
+pub async fn get_collections(
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<CollectionResponse>>> {
+    let collections: Vec<CollectionSummary> = db
+        .query(
+            "
+            SELECT
+                id,
+                name,
+                created_at,
+                array::len(
+                    SELECT VALUE id FROM bookmarks
+                    WHERE in = $uid AND collections CONTAINS $parent.id
+                ) AS posts_count
+            FROM collections
+            WHERE owner = $uid
+            ORDER BY created_at DESC
+            ",
+        )
+        .bind(("uid", parse_thing(&auth.user_id)?))
+        .await?
+        .take(0)?;
+    Ok(Json(collections.into_iter().map(Into::into).collect()))
+}
+
+/// Adds a post to a collection, saving it first if needed.
+#[put("/add-to-collection/<id>/<post_id>")]
+pub async fn add_to_collection(
+    id: &str,
+    post_id: &str,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let collection = find_collection(db, parse_thing(id)?, &uid).await?;
+    let post = find_post(db, parse_thing(post_id)?).await?;
+    enrich_service::ensure_visible(db, &uid, &post).await?;
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            IF (SELECT id FROM bookmarks WHERE in = $uid AND out = $pid) = [] {
+                RELATE $uid->bookmarks->$pid SET collections = [$cid], created_at = time::now();
+            } ELSE {
+                UPDATE bookmarks SET collections = array::union(collections, [$cid])
+                    WHERE in = $uid AND out = $pid;
+            };
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("uid", uid))
+        .bind(("pid", post.id))
+        .bind(("cid", collection.id))
+        .await?;
+    res.check()?;
+    Ok(format!("Added to collection : {}", collection.name))
+}
+
+/// Takes a post out of a collection. The post stays saved.
+#[delete("/remove-from-collection/<id>/<post_id>")]
+pub async fn remove_from_collection(
+    id: &str,
+    post_id: &str,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let collection = find_collection(db, parse_thing(id)?, &uid).await?;
+    let res = db
+        .query("UPDATE bookmarks SET collections -= $cid WHERE in = $uid AND out = $pid")
+        .bind(("uid", uid))
+        .bind(("pid", parse_thing(post_id)?))
+        .bind(("cid", collection.id))
+        .await?;
+    res.check()?;
+    Ok("Removed from collection".to_string())
+}
+
+async fn find_collection(db: &DB, id: RecordId, owner: &RecordId) -> AppResult<Collection> {
+    let collection = db
+        .query("SELECT * FROM ONLY $id")
+        .bind(("id", id))
+        .await?
+        .take::<Option<Collection>>(0)?
+        .ok_or(AppError::XCustomMessage("Collection not found"))?;
+    if collection.owner != *owner {
+        return Err(AppError::Forbidden("Not your collection"));
+    }
+    Ok(collection)
+}
+
+/// Trims a collection name and checks the trimmed name is 1-100
+/// characters.
+fn check_name(name: &str) -> AppResult<String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_COLLECTION_NAME_LEN {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Name must be 1-100 characters",
+        )));
+    }
+    Ok(name.to_string())
+}
+
+async fn ensure_name_free(db: &DB, owner: &RecordId, name: &str) -> AppResult<()> {
+    let taken: Vec<RecordId> = db
+        .query("SELECT VALUE id FROM collections WHERE owner = $uid AND name = $name")
+        .bind(("uid", owner.clone()))
+        .bind(("name", name.to_string()))
+        .await?
+        .take(0)?;
+    if !taken.is_empty() {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "A collection with this name already exists",
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use rocket::{Route, routes};
+
+use crate::bookmarks::bookmark_service::*;
+
+pub mod bookmark_service;
+pub mod model;
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        toggle_bookmark,
+        get_saved,
+        create_collection,
+        rename_collection,
+        delete_collection,
+        get_collections,
+        add_to_collection,
+        remove_from_collection
+    ]
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+use surrealdb_types::{Datetime, RecordId, SurrealValue, ToSql};
+
+/// A named group of saved posts. Membership lives on the `bookmarks` edge,
+/// so every post in a collection is also in the caller's saved posts.
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct Collection {
+    pub id: RecordId,
+    pub owner: RecordId,
+    pub name: String,
+    pub created_at: Datetime,
+}
+
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct CollectionSummary {
+    pub id: RecordId,
+    pub name: String,
+    pub posts_count: usize,
+    pub created_at: Datetime,
+}
+
+/// When a post on a page of saved posts was saved.
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct SavedPost {
+    pub out: RecordId,
+    pub created_at: Datetime,
+}
+
+/// Longest collection name, counted after trimming.
+pub const MAX_COLLECTION_NAME_LEN: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct CollectionRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CollectionResponse {
+    pub id: String,
+    pub name: String,
+    pub posts_count: usize,
+    pub created_at: Datetime,
+}
+
+impl From<CollectionSummary> for CollectionResponse {
+    fn from(collection: CollectionSummary) -> Self {
+        Self {
+            id: collection.id.to_sql(),
+            name: collection.name,
+            posts_count: collection.posts_count,
+            created_at: collection.created_at,
+        }
+    }
+}
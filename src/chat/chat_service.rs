@@ -135,7 +135,7 @@ pub async fn get_messages(
         }
         let messages: Vec<Message> = res.bind(("limit", limit)).await?.take(0)?;
         let messages: Vec<MessageResponse> = messages.into_iter().map(Into::into).collect();
-        return Ok(Json(messages));
+        Ok(Json(messages))
     }
 }
 
@@ -156,7 +156,7 @@ async fn mark_seen(db: DB, message_id: String) -> Result<String, AppError> {
         .query("UPDATE $message SET status=$status, read_at=$time")
         .bind(("message", parse_thing(&message_id)?))
         .bind(("status", MessageStatus::Seen))
-        .bind(("time", now.clone()))
+        .bind(("time", now))
         .await?;
     res.check()?;
     Ok(now.to_string())
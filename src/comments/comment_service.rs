@@ -0,0 +1,361 @@
+use std::collections::{HashMap, HashSet};
+
+use rocket::{State, delete, get, post, put, serde::json::Json};
+use surrealdb_types::{RecordId, ToSql};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    AppResult, DB,
+    comments::model::{
+        Comment, CommentRequest, CommentResponse, CommentSort, EditCommentRequest,
+        MAX_COMMENT_DEPTH,
+    },
+    db::{make_cursor, parse_cursor, parse_thing},
+    error::AppError,
+    jwt::AuthUser,
+    notifications::notification_service,
+    posts::{enrich_service, model::AuthorSummary, post_service::find_post},
+    users::{mention_service, user_service::is_blocked_between},
+};
+
+#[post("/create-comment/<post_id>", data = "<req>")]
+pub async fn create_comment(
+    post_id: &str,
+    req: Json<CommentRequest>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<CommentResponse>> {
+    req.validate()?;
+    let uid = parse_thing(&auth.user_id)?;
+    let post = find_post(db, parse_thing(post_id)?).await?;
+    if is_blocked_between(db, &uid, &post.uid).await? {
+        return Err(AppError::Forbidden("Cannot comment on this post"));
+    }
+    enrich_service::ensure_visible(db, &uid, &post).await?;
+
+    let (parent, ancestors) = match &req.parent_id {
+        Some(parent_id) => {
+            let parent = find_comment(db, parse_thing(parent_id)?).await?;
+            if parent.post != post.id {
+                return Err(AppError::ValidationError(ValidationError::new(
+                    "Parent comment belongs to another post",
+                )));
+            }
+            if is_blocked_between(db, &uid, &parent.author).await? {
+                return Err(AppError::Forbidden("Cannot reply to this comment"));
+            }
+            if parent.ancestors.len() + 1 > MAX_COMMENT_DEPTH {
+                return Err(AppError::ValidationError(ValidationError::new(
+                    "Reply depth limit reached",
+                )));
+            }
+            let mut ancestors = parent.ancestors;
+            ancestors.push(parent.id.clone());
+            (Some(parent.id), ancestors)
+        }
+        None => (None, Vec::new()),
+    };
+    let mentions = mention_service::resolve_mentions(db, &uid, &req.text).await?;
+
+    let mut res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            CREATE comments SET
+                post = $post,
+                author = $uid,
+                parent = $parent,
+                ancestors = $ancestors,
+                text = $text,
+                mentions = $mentions,
+                likes_count = 0,
+                replies_count = 0,
+                created_at = time::now(),
+                edited_at = NONE;
+            UPDATE $post SET comments_count += 1;
+            IF $parent != NONE {
+                UPDATE $parent SET replies_count += 1;
+            };
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("post", post.id.clone()))
+        .bind(("uid", uid.clone()))
+        .bind(("parent", parent))
+        .bind(("ancestors", ancestors))
+        .bind(("text", req.text.clone()))
+        .bind(("mentions", mentions))
+        .await?;
+    let comment = res
+        .take::<Option<Comment>>(0)?
+        .ok_or(AppError::XCustomMessage("Failed to comment"))?;
+    notification_service::sync_mentions(db, &uid, &post, Some(&comment.id), &comment.mentions)
+        .await?;
+    let comment = enrich_comments(db, &uid, vec![comment])
+        .await?
+        .pop()
+        .ok_or(AppError::XCustomMessage("Failed to comment"))?;
+    Ok(Json(comment))
+}
+
+#[put("/edit-comment/<id>", data = "<req>")]
+pub async fn edit_comment(
+    id: &str,
+    req: Json<EditCommentRequest>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<CommentResponse>> {
+    req.validate()?;
+    let uid = parse_thing(&auth.user_id)?;
+    let comment = find_comment(db, parse_thing(id)?).await?;
+    if comment.author != uid {
+        return Err(AppError::Forbidden("Only the author can edit a comment"));
+    }
+    let comment = db
+        .query("UPDATE $id SET text = $text, mentions = $mentions, edited_at = time::now()")
+        .bind(("id", comment.id))
+        .bind(("text", req.text.clone()))
+        .bind((
+            "mentions",
+            mention_service::resolve_mentions(db, &uid, &req.text).await?,
+        ))
+        .await?
+        .take::<Option<Comment>>(0)?
+        .ok_or(AppError::XCustomMessage("Comment not found"))?;
+    let post = find_post(db, comment.post.clone()).await?;
+    notification_service::sync_mentions(db, &uid, &post, Some(&comment.id), &comment.mentions)
+        .await?;
+    let comment = enrich_comments(db, &uid, vec![comment])
+        .await?
+        .pop()
+        .ok_or(AppError::XCustomMessage("Comment not found"))?;
+    Ok(Json(comment))
+}
+
+#[delete("/delete-comment/<id>")]
+pub async fn delete_comment(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let comment = find_comment(db, parse_thing(id)?).await?;
+    let post = find_post(db, comment.post.clone()).await?;
+    if comment.author != uid && post.uid != uid {
+        return Err(AppError::Forbidden(
+            "Only the author or the post owner can delete a comment",
+        ));
+    }
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $removed = (
+                DELETE comments
+                WHERE post = $post AND (id = $id OR ancestors CONTAINS $id)
+                RETURN BEFORE
+            ).id;
+            DELETE comment_likes WHERE out IN $removed;
+            DELETE notifications WHERE comment IN $removed;
+            UPDATE $post SET comments_count -= array::len($removed);
+            IF $parent != NONE {
+                UPDATE $parent SET replies_count -= 1;
+            };
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("id", comment.id))
+        .bind(("post", comment.post))
+        .bind(("parent", comment.parent))
+        .await?;
+    res.check()?;
+    Ok("Comment deleted".to_string())
+}
+
+#[put("/like-comment/<id>")]
+pub async fn like_comment(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let comment = find_comment(db, parse_thing(id)?).await?;
+    if is_blocked_between(db, &uid, &comment.author).await? {
+        return Err(AppError::Forbidden("Cannot like this comment"));
+    }
+    let post = find_post(db, comment.post).await?;
+    enrich_service::ensure_visible(db, &uid, &post).await?;
+    let mut res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $row = (SELECT * FROM comment_likes WHERE in = $uid AND out = $cid LIMIT 1)[0];
+            IF $row = NONE {
+                RELATE $uid->comment_likes->$cid SET created_at = time::now();
+                UPDATE $cid SET likes_count += 1;
+            } ELSE {
+                DELETE $row.id;
+                UPDATE $cid SET likes_count -= 1;
+            };
+            SELECT VALUE id FROM comment_likes WHERE in = $uid AND out = $cid;
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("uid", uid))
+        .bind(("cid", comment.id))
+        .await?;
+    let liked = res.take::<Option<RecordId>>(2)?;
+    if liked.is_some() {
+        Ok("Liked the comment".to_string())
+    } else {
+        Ok("Unliked the comment".to_string())
+    }
+}
+
+/// A post's comments, or the replies to `parent`. `Top` orders by likes,
+/// then newest first; `Newest` by time alone. Each item carries a `cursor`
+/// to pass back for the next page.
+#[get("/get-comments/<post_id>?<parent>&<sort>&<cursor>&<limit>")]
+pub async fn get_comments(
+    post_id: &str,
+    parent: Option<String>,
+    sort: Option<CommentSort>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<CommentResponse>>> {
+    let uid = parse_thing(&auth.user_id)?;
+    let post = find_post(db, parse_thing(post_id)?).await?;
+    enrich_service::ensure_visible(db, &uid, &post).await?;
+    let limit = limit.unwrap_or(20).min(100);
+    let parent = parent.map(|p| parse_thing(&p)).transpose()?;
+    let sort = sort.unwrap_or(CommentSort::Top);
+    let (cursor_likes, cursor) = match (sort, cursor.as_deref()) {
+        (CommentSort::Top, Some(cursor)) => {
+            let (likes, rest) = parse_top_cursor(cursor)?;
+            (Some(likes), Some(parse_cursor(rest)?))
+        }
+        (CommentSort::Newest, Some(cursor)) => (None, Some(parse_cursor(cursor)?)),
+        (_, None) => (None, None),
+    };
+    let (cursor_at, cursor_id) = cursor.unzip();
+    let sql = match sort {
+        CommentSort::Top => {
+            "
+            SELECT * FROM comments
+            WHERE post = $post
+            AND parent = $parent
+            AND (
+                $cursor_at = NONE
+                OR likes_count < $cursor_likes
+                OR (likes_count = $cursor_likes AND created_at < $cursor_at)
+                OR (likes_count = $cursor_likes AND created_at = $cursor_at AND id < $cursor_id)
+            )
+            ORDER BY likes_count DESC, created_at DESC, id DESC
+            LIMIT $limit
+            "
+        }
+        CommentSort::Newest => {
+            "
+            SELECT * FROM comments
+            WHERE post = $post
+            AND parent = $parent
+            AND (
+                $cursor_at = NONE
+                OR created_at < $cursor_at
+                OR (created_at = $cursor_at AND id < $cursor_id)
+            )
+            ORDER BY created_at DESC, id DESC
+            LIMIT $limit
+            "
+        }
+    };
+    let comments: Vec<Comment> = db
+        .query(sql)
+        .bind(("post", post.id))
+        .bind(("parent", parent))
+        .bind(("cursor_likes", cursor_likes))
+        .bind(("cursor_at", cursor_at))
+        .bind(("cursor_id", cursor_id))
+        .bind(("limit", limit))
+        .await?
+        .take(0)?;
+    let mut comments = enrich_comments(db, &uid, comments).await?;
+    for comment in &mut comments {
+        let cursor = make_cursor(&comment.created_at, &comment.id);
+        comment.cursor = Some(match sort {
+            CommentSort::Top => format!("{}|{cursor}", comment.likes_count),
+            CommentSort::Newest => cursor,
+        });
+    }
+    Ok(Json(comments))
+}
+
+/// Splits a `Top` cursor, `<likes_count>|<created_at>|<id>`, into the like
+/// count and a regular [`parse_cursor`] cursor.
+fn parse_top_cursor(cursor: &str) -> AppResult<(usize, &str)> {
+    cursor
+        .split_once('|')
+        .and_then(|(likes, rest)| Some((likes.parse().ok()?, rest)))
+        .ok_or(AppError::ValidationError(ValidationError::new(
+            "Invalid cursor format",
+        )))
+}
+
+async fn find_comment(db: &DB, id: RecordId) -> AppResult<Comment> {
+    if id.table.as_str() != "comments" {
+        return Err(AppError::XCustomMessage("Comment not found"));
+    }
+    db.query("SELECT * FROM ONLY $id")
+        .bind(("id", id))
+        .await?
+        .take::<Option<Comment>>(0)?
+        .ok_or(AppError::XCustomMessage("Comment not found"))
+}
+
+/// Resolves `liked_by_user` and author summaries for a page of comments in
+/// one round trip.
+async fn enrich_comments(
+    db: &DB,
+    viewer: &RecordId,
+    comments: Vec<Comment>,
+) -> AppResult<Vec<CommentResponse>> {
+    if comments.is_empty() {
+        return Ok(Vec::new());
+    }
+    let comment_ids: Vec<RecordId> = comments.iter().map(|c| c.id.clone()).collect();
+    let mut author_ids: Vec<RecordId> = Vec::new();
+    for comment in &comments {
+        if !author_ids.contains(&comment.author) {
+            author_ids.push(comment.author.clone());
+        }
+    }
+    let mut res = db
+        .query(
+            "
+            SELECT VALUE out FROM comment_likes
+                WHERE in = $viewer
+                AND out IN $comments;
+            SELECT id, username, profile_picture FROM $authors;
+            ",
+        )
+        .bind(("viewer", viewer.clone()))
+        .bind(("comments", comment_ids))
+        .bind(("authors", author_ids))
+        .await?;
+    let liked: HashSet<String> = res
+        .take::<Vec<RecordId>>(0)?
+        .iter()
+        .map(|id| id.to_sql())
+        .collect();
+    let authors: HashMap<String, AuthorSummary> = res
+        .take::<Vec<AuthorSummary>>(1)?
+        .into_iter()
+        .map(|a| (a.id.to_sql(), a))
+        .collect();
+    Ok(comments
+        .into_iter()
+        .map(|comment| {
+            let author = authors
+                .get(&comment.author.to_sql())
+                .map(|a| a.clone().into());
+            let mut response: CommentResponse = comment.into();
+            response.liked_by_user = liked.contains(&response.id);
+            response.author = author;
+            response
+        })
+        .collect())
+}
@@ -0,0 +1,16 @@
+use rocket::{Route, routes};
+
+use crate::comments::comment_service::*;
+
+pub mod comment_service;
+pub mod model;
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        create_comment,
+        edit_comment,
+        delete_comment,
+        like_comment,
+        get_comments
+    ]
+}
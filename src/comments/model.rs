@@ -0,0 +1,85 @@
+use rocket::FromFormField;
+use serde::{Deserialize, Serialize};
+use surrealdb_types::{Datetime, RecordId, SurrealValue, ToSql};
+use validator::Validate;
+
+use crate::{
+    posts::model::AuthorSummaryResponse,
+    users::model::{Mention, MentionResponse},
+};
+
+/// Replies deeper than this are rejected.
+pub const MAX_COMMENT_DEPTH: usize = 3;
+
+#[derive(Debug, Serialize, Deserialize, SurrealValue)]
+pub struct Comment {
+    pub id: RecordId,
+    pub post: RecordId,
+    pub author: RecordId,
+    pub parent: Option<RecordId>,
+    pub ancestors: Vec<RecordId>,
+    pub text: String,
+    #[surreal(default)]
+    pub mentions: Vec<Mention>,
+    pub likes_count: usize,
+    pub replies_count: usize,
+    pub created_at: Datetime,
+    pub edited_at: Option<Datetime>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CommentRequest {
+    #[validate(length(min = 1, max = 2200, message = "Comment must be 1-2200 characters"))]
+    pub text: String,
+    pub parent_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct EditCommentRequest {
+    #[validate(length(min = 1, max = 2200, message = "Comment must be 1-2200 characters"))]
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum CommentSort {
+    Top,
+    Newest,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommentResponse {
+    pub id: String,
+    pub post: String,
+    pub author: Option<AuthorSummaryResponse>,
+    pub parent: Option<String>,
+    pub depth: usize,
+    pub text: String,
+    pub mentions: Vec<MentionResponse>,
+    pub likes_count: usize,
+    pub replies_count: usize,
+    pub created_at: Datetime,
+    pub edited_at: Option<Datetime>,
+    pub liked_by_user: bool,
+    /// Set on `get-comments`; pass it back to continue after this comment.
+    pub cursor: Option<String>,
+}
+
+impl From<Comment> for CommentResponse {
+    fn from(comment: Comment) -> Self {
+        Self {
+            id: comment.id.to_sql(),
+            post: comment.post.to_sql(),
+            author: None,
+            parent: comment.parent.map(|p| p.to_sql()),
+            depth: comment.ancestors.len(),
+            text: comment.text,
+            mentions: comment.mentions.into_iter().map(Into::into).collect(),
+            likes_count: comment.likes_count,
+            replies_count: comment.replies_count,
+            created_at: comment.created_at,
+            edited_at: comment.edited_at,
+            liked_by_user: false,
+            cursor: None,
+        }
+    }
+}
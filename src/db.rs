@@ -2,10 +2,11 @@ use surrealdb::{
     Surreal,
     engine::remote::ws::{Client, Wss},
     opt::auth::Root,
-    types::RecordId,
+    types::{Datetime, RecordId, SurrealValue},
 };
+use validator::ValidationError;
 
-use crate::{AppResult, error::AppError};
+use crate::{AppResult, error::AppError, hashtags::hashtag_service, posts::timeline_service};
 
 pub async fn init() -> AppResult<Surreal<Client>> {
     let db =
@@ -18,9 +19,173 @@ pub async fn init() -> AppResult<Surreal<Client>> {
     .await?;
     db.use_ns("main").await?;
     db.use_db("main").await?;
+    define_schema(&db).await?;
     Ok(db)
 }
 
+async fn define_schema(db: &Surreal<Client>) -> AppResult<()> {
+    db.query(
+        "
+        DEFINE INDEX IF NOT EXISTS timeline_owner ON timeline FIELDS owner, created_at;
+        DEFINE INDEX IF NOT EXISTS timeline_author ON timeline FIELDS owner, author;
+        DEFINE INDEX IF NOT EXISTS reactions_pair ON reactions FIELDS in, out UNIQUE;
+        DEFINE INDEX IF NOT EXISTS reactions_post ON reactions FIELDS out, created_at;
+        DEFINE INDEX IF NOT EXISTS comments_thread ON comments FIELDS post, parent, created_at;
+        DEFINE INDEX IF NOT EXISTS timeline_post ON timeline FIELDS post;
+        DEFINE INDEX IF NOT EXISTS post_edits_post ON post_edits FIELDS post, edited_at;
+        DEFINE INDEX IF NOT EXISTS comment_likes_pair ON comment_likes FIELDS in, out UNIQUE;
+        DEFINE INDEX IF NOT EXISTS transcode_jobs_queue ON transcode_jobs FIELDS status, created_at;
+        DEFINE INDEX IF NOT EXISTS transcode_jobs_post ON transcode_jobs FIELDS post;
+        DEFINE INDEX IF NOT EXISTS stories_author ON stories FIELDS uid, expires_at;
+        DEFINE INDEX IF NOT EXISTS stories_expiry ON stories FIELDS expires_at;
+        DEFINE INDEX IF NOT EXISTS story_views_pair ON story_views FIELDS in, out UNIQUE;
+        DEFINE INDEX IF NOT EXISTS story_views_story ON story_views FIELDS out, viewed_at;
+        DEFINE INDEX IF NOT EXISTS tagged_pair ON tagged FIELDS in, out UNIQUE;
+        DEFINE INDEX IF NOT EXISTS tagged_tag ON tagged FIELDS out, created_at;
+        DEFINE INDEX IF NOT EXISTS hashtag_follows_pair ON hashtag_follows FIELDS in, out UNIQUE;
+        DEFINE INDEX IF NOT EXISTS media_blobs_refs ON media_blobs FIELDS refs, updated_at;
+        DEFINE INDEX IF NOT EXISTS blocks_pair ON blocks FIELDS in, out UNIQUE;
+        DEFINE INDEX IF NOT EXISTS mutes_pair ON mutes FIELDS in, out UNIQUE;
+        DEFINE INDEX IF NOT EXISTS close_friends_pair ON close_friends FIELDS in, out UNIQUE;
+        DEFINE INDEX IF NOT EXISTS notifications_recipient ON notifications FIELDS recipient, created_at;
+        DEFINE INDEX IF NOT EXISTS notifications_subject ON notifications FIELDS post, comment;
+        DEFINE INDEX IF NOT EXISTS posts_original ON posts FIELDS original, uid;
+        DEFINE INDEX IF NOT EXISTS posts_pinned ON posts FIELDS uid, pinned_at;
+        DEFINE INDEX IF NOT EXISTS bookmarks_pair ON bookmarks FIELDS in, out UNIQUE;
+        DEFINE INDEX IF NOT EXISTS bookmarks_saved ON bookmarks FIELDS in, created_at;
+        DEFINE INDEX IF NOT EXISTS collections_owner ON collections FIELDS owner, name UNIQUE;
+        DEFINE INDEX IF NOT EXISTS poll_votes_pair ON poll_votes FIELDS in, out UNIQUE;
+        DEFINE INDEX IF NOT EXISTS post_views_window ON post_views FIELDS post, viewer, bucket UNIQUE;
+        DEFINE INDEX IF NOT EXISTS post_views_post ON post_views FIELDS post, created_at;
+        DEFINE INDEX IF NOT EXISTS post_views_viewer ON post_views FIELDS viewer, author, created_at;
+        DEFINE INDEX IF NOT EXISTS follows_source ON follows FIELDS source_post;
+        DEFINE INDEX IF NOT EXISTS engagement_snapshots_post ON engagement_snapshots FIELDS post, taken_at;
+        DEFINE INDEX IF NOT EXISTS engagement_snapshots_taken ON engagement_snapshots FIELDS taken_at;
+        DEFINE INDEX IF NOT EXISTS trending_posts_score ON trending_posts FIELDS score;
+        DEFINE INDEX IF NOT EXISTS tagged_created ON tagged FIELDS created_at;
+        DEFINE INDEX IF NOT EXISTS drafts_owner ON drafts FIELDS uid, updated_at;
+        DEFINE INDEX IF NOT EXISTS drafts_due ON drafts FIELDS publish_at;
+        ",
+    )
+    .await?
+    .check()?;
+    define_media_keys(db).await?;
+    migrate_likes_to_reactions(db).await?;
+    migrate_post_content_to_media(db).await?;
+    migrate_media_urls_to_keys(db).await?;
+    backfill_hashtags(db).await?;
+    backfill_timelines(db).await?;
+    Ok(())
+}
+
+/// Every storage key a record's media refers to: originals, renditions and
+/// posters.
+const MEDIA_KEYS: &str = "
+    array::distinct(array::concat(
+        (media ?? []).storage_key,
+        array::flatten((media ?? []).renditions ?? []).storage_key,
+        array::flatten((media ?? []).poster ?? []).storage_key
+    ))
+";
+
+/// Keeps `media_keys` on posts, drafts and stories in step with their media
+/// and indexes it, so media requests can find the records carrying a key
+/// without scanning. Records written before the field existed get it here.
+async fn define_media_keys(db: &Surreal<Client>) -> AppResult<()> {
+    db.query(format!(
+        "
+        DEFINE FIELD IF NOT EXISTS media_keys ON posts VALUE {MEDIA_KEYS};
+        DEFINE FIELD IF NOT EXISTS media_keys ON drafts VALUE {MEDIA_KEYS};
+        DEFINE FIELD IF NOT EXISTS media_keys ON stories VALUE {MEDIA_KEYS};
+        DEFINE INDEX IF NOT EXISTS posts_media_keys ON posts FIELDS media_keys.*;
+        DEFINE INDEX IF NOT EXISTS drafts_media_keys ON drafts FIELDS media_keys.*;
+        DEFINE INDEX IF NOT EXISTS stories_media_keys ON stories FIELDS media_keys.*;
+        UPDATE posts, drafts, stories SET media_keys = NONE WHERE media_keys = NONE;
+        "
+    ))
+    .await?
+    .check()?;
+    Ok(())
+}
+
+/// Whether the one-time migration `name` has completed.
+async fn migration_done(db: &Surreal<Client>, name: &str) -> AppResult<bool> {
+    let done: Option<RecordId> = db
+        .query("SELECT VALUE id FROM ONLY $id")
+        .bind(("id", RecordId::new("migrations", name)))
+        .await?
+        .take(0)?;
+    Ok(done.is_some())
+}
+
+async fn mark_migration_done(db: &Surreal<Client>, name: &str) -> AppResult<()> {
+    db.query("UPSERT $id SET completed_at = time::now()")
+        .bind(("id", RecordId::new("migrations", name)))
+        .await?
+        .check()?;
+    Ok(())
+}
+
+/// Fills the materialized timeline for follows created before it existed.
+/// Pairs that already have timeline entries are skipped, so an interrupted
+/// run can simply be repeated.
+async fn backfill_timelines(db: &Surreal<Client>) -> AppResult<()> {
+    const NAME: &str = "timeline_backfill";
+    if migration_done(db, NAME).await? {
+        return Ok(());
+    }
+    let follows: Vec<FollowPair> = db
+        .query(
+            "
+            SELECT follower_id, following_id FROM follows
+            WHERE (
+                SELECT id FROM timeline
+                WHERE owner = $parent.follower_id
+                AND author = $parent.following_id
+                LIMIT 1
+            ) = [];
+            ",
+        )
+        .await?
+        .take(0)?;
+    for follow in follows {
+        timeline_service::backfill(db, follow.follower_id, follow.following_id).await?;
+    }
+    mark_migration_done(db, NAME).await
+}
+
+#[derive(Debug, SurrealValue)]
+struct FollowPair {
+    follower_id: RecordId,
+    following_id: RecordId,
+}
+
+/// Moves the legacy `likes` documents (one `user_ids` array per post) into
+/// per-user `reactions` edges. Migrated documents are deleted, so this is a
+/// no-op once the table is empty.
+async fn migrate_likes_to_reactions(db: &Surreal<Client>) -> AppResult<()> {
+    db.query(
+        "
+        BEGIN TRANSACTION;
+        FOR $row IN (SELECT * FROM likes) {
+            LET $post = $row.post_id;
+            FOR $user IN ($row.user_ids ?? []) {
+                IF (SELECT id FROM reactions WHERE in = $user AND out = $post) = [] {
+                    RELATE $user->reactions->$post SET
+                        reaction = 'like',
+                        created_at = time::now();
+                };
+            };
+            DELETE $row.id;
+        };
+        COMMIT TRANSACTION;
+        ",
+    )
+    .await?
+    .check()?;
+    Ok(())
+}
+
 pub fn parse_thing_to_record(id: &str) -> AppResult<(String, String)> {
     let (table, record_id) = id
         .split_once(':')
@@ -36,3 +201,88 @@ pub fn parse_thing(id: &str) -> AppResult<RecordId> {
 
     Ok(RecordId::new(table, record_id))
 }
+
+/// Keyset cursor for lists ordered by `(created_at, id)` newest first:
+/// `<created_at>|<id>` of the last item on the page. Carrying the id means
+/// rows that share a timestamp are neither skipped nor repeated.
+pub fn make_cursor(created_at: &Datetime, id: &str) -> String {
+    format!("{}|{}", created_at.to_rfc3339(), id)
+}
+
+pub fn parse_cursor(cursor: &str) -> AppResult<(Datetime, RecordId)> {
+    let invalid = || AppError::ValidationError(ValidationError::new("Invalid cursor format"));
+    let (created_at, id) = cursor.split_once('|').ok_or_else(invalid)?;
+    let created_at = created_at.parse::<Datetime>().map_err(|_| invalid())?;
+    let id = parse_thing(id).map_err(|_| invalid())?;
+    Ok((created_at, id))
+}
+
+/// Rewrites single-image posts (`content` URL string) into the `media`
+/// array layout used by carousel posts.
+async fn migrate_post_content_to_media(db: &Surreal<Client>) -> AppResult<()> {
+    db.query(
+        "
+        UPDATE posts SET
+            media = [{
+                position: 0,
+                kind: 'image',
+                storage_key: 'posts/' + array::last(string::split(content, '/')),
+                url: content,
+                width: NONE,
+                height: NONE,
+                alt_text: NONE
+            }],
+            content = NONE
+        WHERE media = NONE AND content != NONE;
+        ",
+    )
+    .await?
+    .check()?;
+    Ok(())
+}
+
+/// Strips the hardcoded `http://localhost:8080/` origin from stored media so
+/// only storage keys remain; URLs are built at response time.
+async fn migrate_media_urls_to_keys(db: &Surreal<Client>) -> AppResult<()> {
+    db.query(
+        "
+        UPDATE users SET
+            profile_picture = string::replace(profile_picture, $origin, '')
+        WHERE string::starts_with(profile_picture ?? '', $origin);
+        ",
+    )
+    .bind(("origin", "http://localhost:8080/"))
+    .await?
+    .check()?;
+    Ok(())
+}
+
+/// Indexes hashtags of posts created before tags were extracted: posts with
+/// a `#` in their caption but no `tagged` edges yet. Runs once; later posts
+/// are tagged when they are created or edited.
+async fn backfill_hashtags(db: &Surreal<Client>) -> AppResult<()> {
+    const NAME: &str = "hashtag_backfill";
+    if migration_done(db, NAME).await? {
+        return Ok(());
+    }
+    let posts: Vec<UntaggedPost> = db
+        .query(
+            "
+            SELECT id, caption FROM posts
+            WHERE string::contains(caption ?? '', '#')
+            AND array::len(->tagged) = 0;
+            ",
+        )
+        .await?
+        .take(0)?;
+    for post in posts {
+        hashtag_service::sync_post_tags(db, &post.id, &post.caption).await?;
+    }
+    mark_migration_done(db, NAME).await
+}
+
+#[derive(Debug, SurrealValue)]
+struct UntaggedPost {
+    id: RecordId,
+    caption: String,
+}
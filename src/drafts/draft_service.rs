@@ -0,0 +1,324 @@
+use std::str::FromStr;
+
+use chrono::Utc;
+use rocket::{State, delete, form::Form, get, post, put, serde::json::Json};
+use surrealdb_types::{Datetime, RecordId};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    AppResult, DB, Store,
+    db::{parse_thing, parse_thing_to_record},
+    drafts::model::{Draft, DraftResponse, EditDraftRequest},
+    error::AppError,
+    jwt::AuthUser,
+    posts::{
+        enrich_service,
+        model::{NewPost, Poll, Post, PostFormRequest, PostResponse},
+        poll_service,
+        post_service::{self, check_caption, release_media, resolve_post_kind, save_form_media},
+    },
+};
+
+/// Saves a post for later. Takes the same multipart body as `/post`; media
+/// is stored right away so publishing is instant. With `publish_at` the
+/// draft is published automatically at that time. Videos are transcoded
+/// once the draft is published.
+#[post("/draft?<publish_at>", data = "<form>", format = "multipart/form-data")]
+pub async fn create_draft(
+    publish_at: Option<String>,
+    mut form: Form<PostFormRequest<'_>>,
+    db: &State<DB>,
+    store: &State<Store>,
+    auth: AuthUser,
+) -> AppResult<Json<DraftResponse>> {
+    let uid = parse_thing(&auth.user_id)?;
+    let kind = resolve_post_kind(&form)?;
+    let publish_at = publish_at.as_deref().map(parse_publish_at).transpose()?;
+    let poll = poll_service::resolve_poll(&form)?;
+    check_poll_end(poll.as_ref(), publish_at.as_ref())?;
+    let (_, owner) = parse_thing_to_record(&auth.user_id)?;
+    let media = save_form_media(db, store.inner().as_ref(), &owner, &mut form).await?;
+    let res = db
+        .query(
+            "
+            CREATE ONLY drafts SET
+                uid = $uid,
+                kind = $kind,
+                media = $media,
+                caption = $caption,
+                link_url = $link_url,
+                visibility = $visibility,
+                poll = $poll,
+                publish_at = $publish_at,
+                created_at = time::now(),
+                updated_at = time::now();
+            ",
+        )
+        .bind(("uid", uid))
+        .bind(("kind", kind))
+        .bind(("media", media.clone()))
+        .bind(("caption", form.caption.trim().to_string()))
+        .bind((
+            "link_url",
+            form.link_url.as_ref().map(|url| url.trim().to_string()),
+        ))
+        .bind(("visibility", form.visibility.unwrap_or_default()))
+        .bind(("poll", poll))
+        .bind(("publish_at", publish_at))
+        .await;
+    let draft = match res.and_then(|mut res| res.take::<Option<Draft>>(0)) {
+        Ok(Some(draft)) => draft,
+        Ok(None) => {
+            release_media(db, store.inner().as_ref(), &media).await?;
+            return Err(AppError::XCustomMessage("Failed to save draft"));
+        }
+        Err(e) => {
+            release_media(db, store.inner().as_ref(), &media).await?;
+            return Err(e.into());
+        }
+    };
+    Ok(Json(draft.into()))
+}
+
+/// The caller's drafts. `scheduled=true` lists only scheduled posts, soonest
+/// first; `scheduled=false` only unscheduled drafts. Otherwise everything,
+/// most recently edited first.
+#[get("/drafts?<scheduled>")]
+pub async fn get_drafts(
+    scheduled: Option<bool>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<DraftResponse>>> {
+    let query = if scheduled == Some(true) {
+        "SELECT * FROM drafts WHERE uid = $uid AND publish_at != NONE ORDER BY publish_at ASC"
+    } else {
+        "
+        SELECT * FROM drafts
+        WHERE uid = $uid
+        AND ($scheduled = NONE OR publish_at = NONE)
+        ORDER BY updated_at DESC
+        "
+    };
+    let drafts: Vec<Draft> = db
+        .query(query)
+        .bind(("uid", parse_thing(&auth.user_id)?))
+        .bind(("scheduled", scheduled))
+        .await?
+        .take(0)?;
+    Ok(Json(drafts.into_iter().map(Into::into).collect()))
+}
+
+/// Changes the caption, visibility or scheduled time of a draft. Omitted
+/// fields are left as they are.
+#[put("/edit-draft/<id>", data = "<req>")]
+pub async fn edit_draft(
+    id: &str,
+    req: Json<EditDraftRequest>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<DraftResponse>> {
+    req.validate()?;
+    let uid = parse_thing(&auth.user_id)?;
+    let draft = find_draft(db, parse_thing(id)?, &uid).await?;
+    ensure_not_publishing(&draft)?;
+    let caption = match &req.caption {
+        Some(caption) => {
+            check_caption(draft.kind, caption)?;
+            caption.trim().to_string()
+        }
+        None => draft.caption,
+    };
+    let publish_at = match req.publish_at.as_deref() {
+        Some(publish_at) => Some(parse_publish_at(publish_at)?),
+        None => draft.publish_at,
+    };
+    check_poll_end(draft.poll.as_ref(), publish_at.as_ref())?;
+    let draft = db
+        .query(
+            "
+            UPDATE ONLY $id SET
+                caption = $caption,
+                visibility = $visibility,
+                publish_at = $publish_at,
+                error = NONE,
+                updated_at = time::now();
+            ",
+        )
+        .bind(("id", draft.id))
+        .bind(("caption", caption))
+        .bind(("visibility", req.visibility.unwrap_or(draft.visibility)))
+        .bind(("publish_at", publish_at))
+        .await?
+        .take::<Option<Draft>>(0)?
+        .ok_or(AppError::XCustomMessage("Draft not found"))?;
+    Ok(Json(draft.into()))
+}
+
+/// Unschedules a post, keeping it as a draft.
+#[put("/cancel-schedule/<id>")]
+pub async fn cancel_schedule(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let draft = find_draft(db, parse_thing(id)?, &uid).await?;
+    ensure_not_publishing(&draft)?;
+    if draft.publish_at.is_none() {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Draft is not scheduled",
+        )));
+    }
+    let res = db
+        .query("UPDATE $id SET publish_at = NONE, updated_at = time::now()")
+        .bind(("id", draft.id))
+        .await?;
+    res.check()?;
+    Ok("Schedule cancelled".to_string())
+}
+
+/// Publishes a draft now, whether or not it is scheduled.
+#[post("/publish-draft/<id>")]
+pub async fn publish_draft(
+    id: &str,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<PostResponse>> {
+    let uid = parse_thing(&auth.user_id)?;
+    let draft = find_draft(db, parse_thing(id)?, &uid).await?;
+    // Claim the draft so the scheduler cannot publish it a second time.
+    let draft = db
+        .query("UPDATE $id SET publishing = true WHERE publishing != true RETURN AFTER")
+        .bind(("id", draft.id))
+        .await?
+        .take::<Vec<Draft>>(0)?
+        .into_iter()
+        .next()
+        .ok_or(AppError::ValidationError(ValidationError::new(
+            "Draft is already being published",
+        )))?;
+    let post = publish(db, draft).await?;
+    let post = enrich_service::enrich_post(db, &uid, post).await?;
+    Ok(Json(post))
+}
+
+#[delete("/delete-draft/<id>")]
+pub async fn delete_draft(
+    id: &str,
+    db: &State<DB>,
+    store: &State<Store>,
+    auth: AuthUser,
+) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let draft = find_draft(db, parse_thing(id)?, &uid).await?;
+    let deleted = db
+        .query("DELETE $id WHERE publishing != true RETURN BEFORE")
+        .bind(("id", draft.id))
+        .await?
+        .take::<Vec<Draft>>(0)?;
+    if deleted.is_empty() {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Draft is being published",
+        )));
+    }
+    release_media(db, store.inner().as_ref(), &draft.media).await?;
+    Ok("Draft deleted".to_string())
+}
+
+/// Turns a claimed draft into a post. The draft is deleted in the
+/// transaction that creates the post. If the post cannot be created, the
+/// claim is released and the draft unscheduled with the error noted, so its
+/// media is not lost; failures after that point leave the post in place.
+pub async fn publish(db: &DB, draft: Draft) -> AppResult<Post> {
+    let published = post_service::publish_post(
+        db,
+        &draft.uid,
+        NewPost {
+            kind: draft.kind,
+            media: draft.media,
+            caption: draft.caption,
+            link_url: draft.link_url,
+            visibility: draft.visibility,
+            poll: draft.poll,
+            draft: Some(draft.id.clone()),
+        },
+    )
+    .await;
+    if let Err(e) = &published {
+        release_claim(db, &draft.id, &e.to_string()).await?;
+    }
+    published
+}
+
+/// Hands a claimed draft back to its owner. Does nothing once the post has
+/// been created, since that deletes the draft.
+async fn release_claim(db: &DB, id: &RecordId, error: &str) -> AppResult<()> {
+    let res = db
+        .query(
+            "
+            UPDATE $id SET
+                publishing = false,
+                publish_at = NONE,
+                error = $error,
+                updated_at = time::now()
+            WHERE publishing = true;
+            ",
+        )
+        .bind(("id", id.clone()))
+        .bind(("error", error.to_string()))
+        .await?;
+    res.check()?;
+    Ok(())
+}
+
+/// Puts back drafts claimed by a process that stopped before publishing
+/// them. A draft whose post was created is already gone.
+pub async fn release_stale_claims(db: &DB) -> AppResult<()> {
+    db.query("UPDATE drafts SET publishing = false WHERE publishing = true")
+        .await?
+        .check()?;
+    Ok(())
+}
+
+fn ensure_not_publishing(draft: &Draft) -> AppResult<()> {
+    if draft.publishing {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Draft is being published",
+        )));
+    }
+    Ok(())
+}
+
+/// Parses an RFC 3339 publish time, which must lie in the future.
+fn parse_publish_at(publish_at: &str) -> AppResult<Datetime> {
+    let publish_at = Datetime::from_str(publish_at).map_err(|_| {
+        AppError::ValidationError(ValidationError::new("Invalid publish_at format"))
+    })?;
+    if *publish_at <= Utc::now() {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "publish_at must be in the future",
+        )));
+    }
+    Ok(publish_at)
+}
+
+/// A scheduled post's poll has to still be open when the post goes out.
+fn check_poll_end(poll: Option<&Poll>, publish_at: Option<&Datetime>) -> AppResult<()> {
+    if let (Some(poll), Some(publish_at)) = (poll, publish_at)
+        && poll.ends_at <= *publish_at
+    {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Poll must end after the scheduled time",
+        )));
+    }
+    Ok(())
+}
+
+async fn find_draft(db: &DB, id: RecordId, owner: &RecordId) -> AppResult<Draft> {
+    let draft = db
+        .query("SELECT * FROM ONLY $id")
+        .bind(("id", id))
+        .await?
+        .take::<Option<Draft>>(0)?
+        .ok_or(AppError::XCustomMessage("Draft not found"))?;
+    if draft.uid != *owner {
+        return Err(AppError::Forbidden("Not your draft"));
+    }
+    Ok(draft)
+}
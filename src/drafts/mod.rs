@@ -0,0 +1,18 @@
+use rocket::{Route, routes};
+
+use crate::drafts::draft_service::*;
+
+pub mod draft_service;
+pub mod model;
+pub mod scheduler_service;
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        create_draft,
+        get_drafts,
+        edit_draft,
+        cancel_schedule,
+        publish_draft,
+        delete_draft
+    ]
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use surrealdb_types::{Datetime, RecordId, SurrealValue, ToSql};
+use validator::Validate;
+
+use crate::posts::model::{Media, MediaResponse, Poll, PollResponse, PostKind, PostVisibility};
+
+/// A post composed but not yet published. Its media is already stored, so
+/// publishing only has to create the post. Drafts with `publish_at` set are
+/// published by the scheduler once that time has passed.
+#[derive(Debug, Serialize, Deserialize, SurrealValue)]
+pub struct Draft {
+    pub id: RecordId,
+    pub uid: RecordId,
+    pub kind: PostKind,
+    pub media: Vec<Media>,
+    pub caption: String,
+    pub link_url: Option<String>,
+    #[surreal(default)]
+    pub visibility: PostVisibility,
+    #[surreal(default)]
+    pub poll: Option<Poll>,
+    pub publish_at: Option<Datetime>,
+    /// Why the last scheduled publish failed, if it did.
+    #[surreal(default)]
+    pub error: Option<String>,
+    /// Set while the draft is being turned into a post, so it is published
+    /// once and not edited or deleted meanwhile.
+    #[surreal(default)]
+    pub publishing: bool,
+    pub created_at: Datetime,
+    pub updated_at: Datetime,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct EditDraftRequest {
+    #[validate(length(max = 5000, message = "Caption must be at most 5000 characters"))]
+    pub caption: Option<String>,
+    pub visibility: Option<PostVisibility>,
+    /// RFC 3339; must be in the future. Use `/cancel-schedule` to unset.
+    pub publish_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DraftResponse {
+    pub id: String,
+    pub kind: PostKind,
+    pub media: Vec<MediaResponse>,
+    pub caption: String,
+    pub link_url: Option<String>,
+    pub visibility: PostVisibility,
+    pub poll: Option<PollResponse>,
+    pub publish_at: Option<Datetime>,
+    pub error: Option<String>,
+    pub created_at: Datetime,
+    pub updated_at: Datetime,
+}
+
+impl From<Draft> for DraftResponse {
+    fn from(draft: Draft) -> Self {
+        let mut media: Vec<MediaResponse> = draft.media.into_iter().map(Into::into).collect();
+        // Nobody but the owner should be able to fetch unpublished media.
+        media.iter_mut().for_each(MediaResponse::sign);
+        Self {
+            id: draft.id.to_sql(),
+            kind: draft.kind,
+            media,
+            caption: draft.caption,
+            link_url: draft.link_url,
+            visibility: draft.visibility,
+            poll: draft.poll.map(Into::into),
+            publish_at: draft.publish_at,
+            error: draft.error,
+            created_at: draft.created_at,
+            updated_at: draft.updated_at,
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use rocket::tokio;
+
+use surrealdb_types::ToSql;
+
+use crate::{
+    AppResult, DB,
+    drafts::{draft_service, model::Draft},
+};
+
+/// How often due scheduled posts are looked for.
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Runs the post scheduler forever. Drafts left claimed by a previous
+/// process are released first.
+pub async fn run(db: DB) {
+    if let Err(e) = draft_service::release_stale_claims(&db).await {
+        eprintln!("post scheduler: failed to release drafts: {}", e);
+    }
+    loop {
+        if let Err(e) = publish_due(&db).await {
+            eprintln!("post scheduler: {}", e);
+        }
+        tokio::time::sleep(SCHEDULE_INTERVAL).await;
+    }
+}
+
+/// Claims every draft whose time has come and publishes it. The post gets
+/// its `created_at` at this point, so it lands at the top of feeds.
+async fn publish_due(db: &DB) -> AppResult<()> {
+    let due: Vec<Draft> = db
+        .query(
+            "
+            UPDATE drafts SET publishing = true
+                WHERE publish_at != NONE
+                AND publish_at <= time::now()
+                AND publishing != true
+                RETURN AFTER;
+            ",
+        )
+        .await?
+        .take(0)?;
+    for draft in due {
+        let id = draft.id.to_sql();
+        if let Err(e) = draft_service::publish(db, draft).await {
+            eprintln!("post scheduler: failed to publish {}: {}", id, e);
+        }
+    }
+    Ok(())
+}
@@ -6,7 +6,7 @@ use thiserror::Error;
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error(transparent)]
-    Rocket(#[from] rocket::Error),
+    Rocket(Box<rocket::Error>),
 
     #[error(transparent)]
     Surreal(#[from] surrealdb::Error),
@@ -17,6 +17,9 @@ pub enum AppError {
     #[error("{0}")]
     XCustomMessage(&'static str),
 
+    #[error("{0}")]
+    Forbidden(&'static str),
+
     #[error(transparent)]
     Jwt(#[from] jsonwebtoken::errors::Error),
 
@@ -33,10 +36,27 @@ pub enum AppError {
     ValidationErrors(#[from] validator::ValidationErrors),
 
     #[error(transparent)]
-    WSError(#[from] rocket_ws::result::Error),
+    WSError(Box<rocket_ws::result::Error>),
 
     #[error(transparent)]
     SerdeError(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+// Large errors are boxed to keep `AppResult` small; build them with `?`
+// or `.into()`.
+impl From<rocket::Error> for AppError {
+    fn from(e: rocket::Error) -> Self {
+        AppError::Rocket(Box::new(e))
+    }
+}
+
+impl From<rocket_ws::result::Error> for AppError {
+    fn from(e: rocket_ws::result::Error) -> Self {
+        AppError::WSError(Box::new(e))
+    }
 }
 
 impl<'r> Responder<'r, 'static> for AppError {
@@ -46,6 +66,7 @@ impl<'r> Responder<'r, 'static> for AppError {
             AppError::Jwt(_) => Status::Unauthorized,
             AppError::ValidationError(_) => Status::BadRequest,
             AppError::ValidationErrors(_) => Status::BadRequest,
+            AppError::Forbidden(_) => Status::Forbidden,
             _ => Status::InternalServerError,
         };
 
@@ -59,9 +80,6 @@ impl<'r> Responder<'r, 'static> for AppError {
 
 impl From<AppError> for WsError {
     fn from(e: AppError) -> Self {
-        WsError::Io(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            e.to_string(),
-        ))
+        WsError::Io(std::io::Error::other(e.to_string()))
     }
 }
@@ -0,0 +1,95 @@
+use rocket::{State, get, serde::json::Json};
+
+use crate::{
+    AppResult, DB,
+    db::parse_thing,
+    explore::model::{TrendingHashtag, TrendingHashtagResponse},
+    jwt::AuthUser,
+    posts::{
+        enrich_service,
+        model::{FeedQuery, Post, PostResponse},
+    },
+};
+
+/// Trending posts from accounts the caller does not follow, highest score
+/// first. Accounts blocked in either direction and muted accounts are left
+/// out, as is anything the caller may not see. The list is recomputed
+/// periodically, so posts deleted, archived or hidden since are skipped
+/// before paging.
+#[get("/posts?<q..>")]
+pub async fn get_explore(
+    db: &State<DB>,
+    auth: AuthUser,
+    q: FeedQuery,
+) -> AppResult<Json<Vec<PostResponse>>> {
+    let page = q.page.unwrap_or(1).max(1);
+    let limit = q.limit.unwrap_or(20).min(50);
+    let uid = parse_thing(&auth.user_id)?;
+    let posts: Vec<Post> = db
+        .query(
+            "
+            LET $following = SELECT VALUE following_id FROM follows WHERE follower_id = $uid;
+            LET $blocked = array::union(
+                SELECT VALUE out FROM blocks WHERE in = $uid,
+                SELECT VALUE in FROM blocks WHERE out = $uid
+            );
+            LET $muted = SELECT VALUE out FROM mutes WHERE in = $uid;
+            LET $ids = (
+                SELECT post, score FROM trending_posts
+                WHERE author != $uid
+                AND author NOT IN $following
+                AND author NOT IN $blocked
+                AND author NOT IN $muted
+                AND post.uid != NONE
+                AND post.archived != true
+                AND (post.status ?? 'ready') = 'ready'
+                AND (post.visibility ?? 'public') = 'public'
+                AND post.uid.is_private != true
+                ORDER BY score DESC
+                LIMIT $limit
+                START $offset
+            ).post;
+            SELECT * FROM $ids;
+            ",
+        )
+        .bind(("uid", uid.clone()))
+        .bind(("limit", limit))
+        .bind(("offset", (page - 1).saturating_mul(limit)))
+        .await?
+        .take(4)?;
+    let posts = enrich_service::enrich_posts(db, &uid, posts).await?;
+    Ok(Json(posts))
+}
+
+/// Hashtags carried by the most public posts over the last day.
+#[get("/hashtags?<limit>")]
+pub async fn get_trending_hashtags(
+    limit: Option<u32>,
+    db: &State<DB>,
+    _auth: AuthUser,
+) -> AppResult<Json<Vec<TrendingHashtagResponse>>> {
+    let tags: Vec<TrendingHashtag> = db
+        .query(
+            "
+            SELECT
+                tag.name AS name,
+                tag.posts_count AS posts_count,
+                count AS recent_posts_count
+            FROM (
+                SELECT out AS tag, count() AS count FROM tagged
+                WHERE created_at > time::now() - 1d
+                AND (in.status ?? 'ready') = 'ready'
+                AND (in.visibility ?? 'public') = 'public'
+                AND in.archived != true
+                AND in.uid.is_private != true
+                GROUP BY tag
+            )
+            ORDER BY recent_posts_count DESC
+            LIMIT $limit
+            ",
+        )
+        .bind(("limit", limit.unwrap_or(10).min(50)))
+        .await?
+        .take(0)?;
+    Ok(Json(tags.into_iter().map(Into::into).collect()))
+}
@@ -0,0 +1,11 @@
+use rocket::{Route, routes};
+
+use crate::explore::explore_service::*;
+
+pub mod explore_service;
+pub mod model;
+pub mod trending_service;
+
+pub fn routes() -> Vec<Route> {
+    routes![get_explore, get_trending_hashtags]
+}
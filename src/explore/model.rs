@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use surrealdb_types::{Datetime, RecordId, SurrealValue};
+
+/// A recent public post considered for trending, with its current counts.
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct TrendingCandidate {
+    pub id: RecordId,
+    pub uid: RecordId,
+    pub likes_count: usize,
+    pub comments_count: usize,
+    pub reposts_count: usize,
+    pub created_at: Datetime,
+}
+
+/// Engagement counts of a post at one point in time. Velocity is the
+/// change between snapshots.
+#[derive(Debug, Serialize, Deserialize, SurrealValue)]
+pub struct EngagementSnapshot {
+    pub post: RecordId,
+    pub likes_count: usize,
+    pub comments_count: usize,
+    pub reposts_count: usize,
+    pub taken_at: Datetime,
+}
+
+#[derive(Debug, Serialize, SurrealValue)]
+pub struct TrendingPost {
+    pub post: RecordId,
+    pub author: RecordId,
+    pub score: f64,
+    pub updated_at: Datetime,
+}
+
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct TrendingHashtag {
+    pub name: String,
+    pub posts_count: i64,
+    pub recent_posts_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrendingHashtagResponse {
+    pub name: String,
+    pub posts_count: i64,
+    /// Public posts tagged within the trending window.
+    pub recent_posts_count: i64,
+}
+
+impl From<TrendingHashtag> for TrendingHashtagResponse {
+    fn from(tag: TrendingHashtag) -> Self {
+        Self {
+            name: tag.name,
+            posts_count: tag.posts_count,
+            recent_posts_count: tag.recent_posts_count,
+        }
+    }
+}
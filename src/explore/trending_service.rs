@@ -0,0 +1,271 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{DateTime, Utc};
+use rocket::tokio;
+use surrealdb_types::{Datetime, ToSql};
+
+use crate::{
+    AppResult, DB,
+    explore::model::{EngagementSnapshot, TrendingCandidate, TrendingPost},
+};
+
+/// How often trending scores are recomputed.
+const TRENDING_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Velocity windows in hours, with the weight of each. The short window
+/// lets fresh bursts rise quickly, the long one keeps steady posts around.
+const WINDOWS: [(i64, f64); 2] = [(1, 3.0), (24, 1.0)];
+/// Posts older than this are not considered.
+const MAX_AGE_HOURS: i64 = 72;
+/// Exponent of the age penalty.
+const GRAVITY: f64 = 1.5;
+/// Most posts of a single author kept in the trending list.
+const MAX_PER_AUTHOR: usize = 2;
+/// Size of the trending list.
+const TRENDING_LIMIT: usize = 500;
+
+/// Runs the trending scorer forever.
+pub async fn run(db: DB) {
+    loop {
+        if let Err(e) = refresh(&db).await {
+            eprintln!("trending: {}", e);
+        }
+        tokio::time::sleep(TRENDING_INTERVAL).await;
+    }
+}
+
+/// Snapshots the engagement of recent public posts and rebuilds the
+/// trending list from how fast it grew.
+async fn refresh(db: &DB) -> AppResult<()> {
+    let now = Utc::now();
+    let mut res = db
+        .query(
+            "
+            SELECT
+                id,
+                uid,
+                likes_count,
+                comments_count ?? 0 AS comments_count,
+                reposts_count ?? 0 AS reposts_count,
+                created_at
+            FROM posts
+            WHERE created_at > $oldest
+            AND (kind ?? 'image') != 'repost'
+            AND (status ?? 'ready') = 'ready'
+            AND (visibility ?? 'public') = 'public'
+            AND archived != true
+            AND uid.is_private != true;
+            SELECT post, likes_count, comments_count, reposts_count, taken_at
+                FROM engagement_snapshots
+                WHERE taken_at > $history_start
+                ORDER BY taken_at ASC;
+            ",
+        )
+        .bind((
+            "oldest",
+            Datetime::from(now - chrono::Duration::hours(MAX_AGE_HOURS)),
+        ))
+        .bind(("history_start", history_start(now)))
+        .await?;
+    let candidates: Vec<TrendingCandidate> = res.take(0)?;
+    let mut history: HashMap<String, Vec<EngagementSnapshot>> = HashMap::new();
+    for snapshot in res.take::<Vec<EngagementSnapshot>>(1)? {
+        history
+            .entry(snapshot.post.to_sql())
+            .or_default()
+            .push(snapshot);
+    }
+
+    let mut scored: Vec<(f64, &TrendingCandidate)> = candidates
+        .iter()
+        .map(|post| {
+            let snapshots = history
+                .get(&post.id.to_sql())
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            (score(post, snapshots, now), post)
+        })
+        .filter(|(score, _)| *score > 0.0)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut per_author: HashMap<String, usize> = HashMap::new();
+    let trending: Vec<TrendingPost> = scored
+        .into_iter()
+        .filter(|(_, post)| {
+            let count = per_author.entry(post.uid.to_sql()).or_default();
+            *count += 1;
+            *count <= MAX_PER_AUTHOR
+        })
+        .take(TRENDING_LIMIT)
+        .map(|(score, post)| TrendingPost {
+            post: post.id.clone(),
+            author: post.uid.clone(),
+            score,
+            updated_at: Datetime::from(now),
+        })
+        .collect();
+    let snapshots: Vec<EngagementSnapshot> = candidates
+        .into_iter()
+        .map(|post| EngagementSnapshot {
+            post: post.id,
+            likes_count: post.likes_count,
+            comments_count: post.comments_count,
+            reposts_count: post.reposts_count,
+            taken_at: Datetime::from(now),
+        })
+        .collect();
+
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            INSERT INTO engagement_snapshots $snapshots;
+            DELETE engagement_snapshots WHERE taken_at <= $history_start;
+            DELETE trending_posts;
+            INSERT INTO trending_posts $trending;
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("snapshots", snapshots))
+        .bind(("trending", trending))
+        .bind(("history_start", history_start(now)))
+        .await?;
+    res.check()?;
+    Ok(())
+}
+
+/// Weighted engagement growth per hour over each window, damped by the
+/// post's age. Comments weigh more than likes and reposts more than
+/// comments. `snapshots` are ordered oldest first.
+fn score(post: &TrendingCandidate, snapshots: &[EngagementSnapshot], now: DateTime<Utc>) -> f64 {
+    let current = engagement(post.likes_count, post.comments_count, post.reposts_count);
+    let created_at = *post.created_at;
+    let velocity: f64 = WINDOWS
+        .iter()
+        .map(|&(hours, weight)| {
+            let start = now - chrono::Duration::hours(hours);
+            // A post created inside the window grew from zero; older posts
+            // are measured from the oldest snapshot inside the window.
+            let (base, since) = if created_at >= start {
+                (0.0, created_at)
+            } else {
+                match snapshots.iter().find(|s| *s.taken_at >= start) {
+                    Some(s) => (
+                        engagement(s.likes_count, s.comments_count, s.reposts_count),
+                        *s.taken_at,
+                    ),
+                    None => return 0.0,
+                }
+            };
+            let elapsed = (now - since).num_minutes().max(15) as f64 / 60.0;
+            weight * (current - base).max(0.0) / elapsed
+        })
+        .sum();
+    let age = (now - created_at).num_minutes().max(0) as f64 / 60.0;
+    velocity / (age + 2.0).powf(GRAVITY)
+}
+
+fn engagement(likes: usize, comments: usize, reposts: usize) -> f64 {
+    likes as f64 + 2.0 * comments as f64 + 3.0 * reposts as f64
+}
+
+/// Snapshots are kept a little longer than the longest window.
+fn history_start(now: DateTime<Utc>) -> Datetime {
+    let hours = WINDOWS.iter().map(|&(hours, _)| hours).max().unwrap_or(0) + 1;
+    Datetime::from(now - chrono::Duration::hours(hours))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use surrealdb_types::RecordId;
+
+    use super::*;
+
+    fn candidate(now: DateTime<Utc>, age_minutes: i64, likes: usize) -> TrendingCandidate {
+        TrendingCandidate {
+            id: RecordId::new("posts", "p"),
+            uid: RecordId::new("users", "u"),
+            likes_count: likes,
+            comments_count: 0,
+            reposts_count: 0,
+            created_at: Datetime::from(now - Duration::minutes(age_minutes)),
+        }
+    }
+
+    fn snapshot(now: DateTime<Utc>, minutes_ago: i64, likes: usize) -> EngagementSnapshot {
+        EngagementSnapshot {
+            post: RecordId::new("posts", "p"),
+            likes_count: likes,
+            comments_count: 0,
+            reposts_count: 0,
+            taken_at: Datetime::from(now - Duration::minutes(minutes_ago)),
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn new_posts_grow_from_zero_in_every_window() {
+        let now = Utc::now();
+        let post = candidate(now, 30, 10);
+        // 10 likes in half an hour: 3 * 20/h + 1 * 20/h.
+        assert_close(score(&post, &[], now), 80.0 / 2.5_f64.powf(GRAVITY));
+    }
+
+    #[test]
+    fn windows_start_at_their_oldest_snapshot() {
+        let now = Utc::now();
+        let post = candidate(now, 30 * 60, 100);
+        let snapshots = [
+            snapshot(now, 25 * 60, 0),
+            snapshot(now, 20 * 60, 40),
+            snapshot(now, 50, 90),
+        ];
+        // 1h window: +10 since 50 minutes ago; 24h window: +60 over 20h.
+        let velocity = 3.0 * 10.0 / (50.0 / 60.0) + 60.0 / 20.0;
+        assert_close(
+            score(&post, &snapshots, now),
+            velocity / 32.0_f64.powf(GRAVITY),
+        );
+    }
+
+    #[test]
+    fn short_elapsed_times_are_clamped() {
+        let now = Utc::now();
+        let post = candidate(now, 2, 5);
+        // Measured over at least 15 minutes, not 2.
+        assert_close(
+            score(&post, &[], now),
+            4.0 * 20.0 / (2.0 + 2.0 / 60.0_f64).powf(GRAVITY),
+        );
+    }
+
+    #[test]
+    fn older_posts_decay_below_younger_ones() {
+        let now = Utc::now();
+        let young = candidate(now, 3 * 60, 50);
+        let old = candidate(now, 20 * 60, 50);
+        let young_history = [snapshot(now, 2 * 60, 0), snapshot(now, 30, 40)];
+        let old_history = [snapshot(now, 2 * 60, 0), snapshot(now, 30, 40)];
+        assert!(score(&young, &young_history, now) > score(&old, &old_history, now));
+    }
+
+    #[test]
+    fn windows_without_snapshots_count_nothing() {
+        let now = Utc::now();
+        let stale = candidate(now, 30 * 60, 1000);
+        assert_eq!(score(&stale, &[], now), 0.0);
+
+        // Only the 24h window, which covers the whole post, contributes.
+        let post = candidate(now, 10 * 60, 100);
+        assert_close(score(&post, &[], now), 10.0 / 12.0_f64.powf(GRAVITY));
+    }
+}
@@ -0,0 +1,303 @@
+use rocket::{State, delete, get, put, serde::json::Json};
+use surrealdb::{Surreal, engine::remote::ws::Client};
+use surrealdb_types::RecordId;
+use validator::ValidationError;
+
+use crate::{
+    AppResult, DB,
+    db::{make_cursor, parse_cursor, parse_thing},
+    error::AppError,
+    hashtags::model::{Hashtag, HashtagResponse},
+    jwt::AuthUser,
+    posts::{
+        enrich_service,
+        model::{Post, PostResponse},
+    },
+};
+
+/// Only the first tags of a caption are indexed.
+pub const MAX_TAGS_PER_POST: usize = 30;
+const MAX_TAG_LEN: usize = 100;
+
+/// Pulls the distinct, normalized hashtags out of a caption. A tag starts
+/// with `#` at a word boundary, runs over letters, digits and `_`, and must
+/// contain at least one letter (`#2024` is not a tag).
+pub fn extract_hashtags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    let mut previous: Option<char> = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let at_boundary = !previous.is_some_and(|p| p.is_alphanumeric() || p == '_');
+        previous = Some(c);
+        if c != '#' || !at_boundary {
+            continue;
+        }
+        let mut end = start + 1;
+        while let Some(&(i, next)) = chars.peek() {
+            if !(next.is_alphanumeric() || next == '_') {
+                break;
+            }
+            end = i + next.len_utf8();
+            previous = Some(next);
+            chars.next();
+        }
+        let tag = text[start + 1..end].to_lowercase();
+        if tag.chars().any(char::is_alphabetic)
+            && tag.chars().count() <= MAX_TAG_LEN
+            && !tags.contains(&tag)
+        {
+            tags.push(tag);
+            if tags.len() == MAX_TAGS_PER_POST {
+                break;
+            }
+        }
+    }
+    tags
+}
+
+/// Brings the `tagged` edges of `post` in line with the hashtags in
+/// `caption`, keeping `posts_count` on each tag up to date. Takes the bare
+/// client so the startup backfill in `db` can use it too.
+pub async fn sync_post_tags(db: &Surreal<Client>, post: &RecordId, caption: &str) -> AppResult<()> {
+    let tags: Vec<RecordId> = extract_hashtags(caption)
+        .into_iter()
+        .map(|tag| RecordId::new("hashtags", tag))
+        .collect();
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $old = SELECT VALUE out FROM tagged WHERE in = $post;
+            LET $removed = array::complement($old, $tags);
+            LET $added = array::complement($tags, $old);
+            DELETE tagged WHERE in = $post AND out IN $removed;
+            UPDATE $removed SET posts_count -= 1;
+            FOR $tag IN $added {
+                UPSERT $tag SET
+                    name = record::id($tag),
+                    posts_count = (posts_count ?? 0) + 1,
+                    followers_count = followers_count ?? 0;
+                RELATE $post->tagged->$tag SET created_at = $post.created_at;
+            };
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("post", post.clone()))
+        .bind(("tags", tags))
+        .await?;
+    res.check()?;
+    Ok(())
+}
+
+#[get("/tag/<name>")]
+pub async fn get_tag(
+    name: &str,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<HashtagResponse>> {
+    let mut res = db
+        .query(
+            "
+            SELECT * FROM ONLY $tag;
+            SELECT VALUE id FROM hashtag_follows WHERE in = $uid AND out = $tag;
+            ",
+        )
+        .bind(("tag", tag_id(name)?))
+        .bind(("uid", parse_thing(&auth.user_id)?))
+        .await?;
+    let tag = res
+        .take::<Option<Hashtag>>(0)?
+        .ok_or(AppError::XCustomMessage("Hashtag not found"))?;
+    let followed = !res.take::<Vec<RecordId>>(1)?.is_empty();
+    let mut response: HashtagResponse = tag.into();
+    response.followed_by_user = followed;
+    Ok(Json(response))
+}
+
+/// Newest public posts carrying a tag, paginated by the `cursor` of the
+/// last post seen. Posts of private accounts only show up for their
+/// followers.
+#[get("/tag-feed/<name>?<cursor>&<limit>")]
+pub async fn get_tag_feed(
+    name: &str,
+    cursor: Option<String>,
+    limit: Option<u32>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<PostResponse>>> {
+    let uid = parse_thing(&auth.user_id)?;
+    let limit = limit.unwrap_or(20);
+    let (cursor_at, cursor_id) = cursor.as_deref().map(parse_cursor).transpose()?.unzip();
+    let posts: Vec<Post> = db
+        .query(
+            "
+            LET $following = SELECT VALUE following_id FROM follows WHERE follower_id = $uid;
+            LET $ids = (SELECT in, created_at FROM tagged
+                WHERE out = $tag
+                AND (
+                    $cursor_at = NONE
+                    OR created_at < $cursor_at
+                    OR (created_at = $cursor_at AND in < $cursor_id)
+                )
+                AND (in.status ?? 'ready') = 'ready'
+                AND (in.visibility ?? 'public') = 'public'
+                AND in.archived != true
+                AND (in.uid.is_private != true OR in.uid = $uid OR in.uid IN $following)
+                ORDER BY created_at DESC, in DESC
+                LIMIT $limit).in;
+            SELECT * FROM $ids;
+            ",
+        )
+        .bind(("tag", tag_id(name)?))
+        .bind(("uid", uid.clone()))
+        .bind(("cursor_at", cursor_at))
+        .bind(("cursor_id", cursor_id))
+        .bind(("limit", limit))
+        .await?
+        .take(2)?;
+    let mut posts = enrich_service::enrich_posts(db, &uid, posts).await?;
+    for post in &mut posts {
+        post.cursor = Some(make_cursor(&post.created_at, &post.id));
+    }
+    Ok(Json(posts))
+}
+
+#[put("/follow-tag/<name>")]
+pub async fn follow_tag(name: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let tag = tag_id(name)?;
+    db.query("SELECT VALUE id FROM ONLY $tag")
+        .bind(("tag", tag.clone()))
+        .await?
+        .take::<Option<RecordId>>(0)?
+        .ok_or(AppError::XCustomMessage("Hashtag not found"))?;
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            IF (SELECT id FROM hashtag_follows WHERE in = $uid AND out = $tag) = [] {
+                RELATE $uid->hashtag_follows->$tag SET created_at = time::now();
+                UPDATE $tag SET followers_count += 1;
+            };
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("uid", parse_thing(&auth.user_id)?))
+        .bind(("tag", tag))
+        .await?;
+    res.check()?;
+    Ok(format!("Followed hashtag : #{}", normalize(name)))
+}
+
+#[delete("/unfollow-tag/<name>")]
+pub async fn unfollow_tag(name: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $edge = SELECT VALUE id FROM hashtag_follows WHERE in = $uid AND out = $tag;
+            IF $edge != [] {
+                DELETE $edge;
+                UPDATE $tag SET followers_count -= 1;
+            };
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("uid", parse_thing(&auth.user_id)?))
+        .bind(("tag", tag_id(name)?))
+        .await?;
+    res.check()?;
+    Ok("Unfollowed hashtag".to_string())
+}
+
+#[get("/followed-tags")]
+pub async fn get_followed_tags(
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<HashtagResponse>>> {
+    let tags: Vec<Hashtag> = db
+        .query(
+            "SELECT * FROM (SELECT VALUE out FROM hashtag_follows WHERE in = $uid) ORDER BY name",
+        )
+        .bind(("uid", parse_thing(&auth.user_id)?))
+        .await?
+        .take(0)?;
+    Ok(Json(
+        tags.into_iter()
+            .map(|tag| {
+                let mut response: HashtagResponse = tag.into();
+                response.followed_by_user = true;
+                response
+            })
+            .collect(),
+    ))
+}
+
+fn normalize(name: &str) -> String {
+    name.trim().trim_start_matches('#').to_lowercase()
+}
+
+fn tag_id(name: &str) -> AppResult<RecordId> {
+    let name = normalize(name);
+    if extract_hashtags(&format!("#{}", name)) != [name.clone()] {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Invalid hashtag",
+        )));
+    }
+    Ok(RecordId::new("hashtags", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_unicode_tags() {
+        assert_eq!(
+            extract_hashtags("Lunch #café #日本 #straße"),
+            ["café", "日本", "straße"]
+        );
+    }
+
+    #[test]
+    fn tags_need_a_letter() {
+        assert_eq!(
+            extract_hashtags("#2024 #2024goals #_ #1_a"),
+            ["2024goals", "1_a"]
+        );
+    }
+
+    #[test]
+    fn trailing_punctuation_ends_a_tag() {
+        assert_eq!(
+            extract_hashtags("#rust! (#tokio), #serde. #axum?#rocket"),
+            ["rust", "tokio", "serde", "axum", "rocket"]
+        );
+    }
+
+    #[test]
+    fn tags_start_at_a_word_boundary() {
+        assert_eq!(
+            extract_hashtags("issue#12 a#b ##double #ok"),
+            ["double", "ok"]
+        );
+    }
+
+    #[test]
+    fn duplicates_are_folded_case_insensitively() {
+        assert_eq!(
+            extract_hashtags("#Rust #rust #RUST #Tokio"),
+            ["rust", "tokio"]
+        );
+    }
+
+    #[test]
+    fn tag_count_and_length_are_capped() {
+        let caption = (0..40)
+            .map(|i| format!("#t{}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(extract_hashtags(&caption).len(), MAX_TAGS_PER_POST);
+        let long = format!("#{} #short", "a".repeat(MAX_TAG_LEN + 1));
+        assert_eq!(extract_hashtags(&long), ["short"]);
+    }
+}
@@ -0,0 +1,16 @@
+use rocket::{Route, routes};
+
+use crate::hashtags::hashtag_service::*;
+
+pub mod hashtag_service;
+pub mod model;
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_tag,
+        get_tag_feed,
+        follow_tag,
+        unfollow_tag,
+        get_followed_tags
+    ]
+}
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+use surrealdb_types::{RecordId, SurrealValue};
+
+/// Tags are stored as `hashtags:⟨name⟩`, with `name` lowercased and
+/// without the leading `#`.
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct Hashtag {
+    pub id: RecordId,
+    pub name: String,
+    pub posts_count: i64,
+    pub followers_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HashtagResponse {
+    pub name: String,
+    pub posts_count: i64,
+    pub followers_count: i64,
+    pub followed_by_user: bool,
+}
+
+impl From<Hashtag> for HashtagResponse {
+    fn from(tag: Hashtag) -> Self {
+        Self {
+            name: tag.name,
+            posts_count: tag.posts_count,
+            followers_count: tag.followers_count,
+            followed_by_user: false,
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use chrono::{Duration, Utc};
+use rocket::{State, get, serde::json::Json};
+use surrealdb_types::{Datetime, ToSql};
+
+use crate::{
+    AppResult, DB,
+    db::parse_thing,
+    error::AppError,
+    insights::model::{DailyCount, PostInsightsResponse},
+    jwt::AuthUser,
+    posts::post_service::find_post,
+};
+
+/// Longest period the daily series can cover.
+const MAX_INSIGHT_DAYS: i64 = 90;
+
+/// Views, reach and engagement of one of the caller's posts. `days`
+/// (default 30) bounds the daily series; the totals cover the post's whole
+/// life.
+#[get("/post/<id>?<days>")]
+pub async fn get_post_insights(
+    id: &str,
+    days: Option<i64>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<PostInsightsResponse>> {
+    let post = find_post(db, parse_thing(id)?).await?;
+    if post.uid != parse_thing(&auth.user_id)? {
+        return Err(AppError::Forbidden("Only the owner can see insights"));
+    }
+    let days = days.unwrap_or(30).clamp(1, MAX_INSIGHT_DAYS);
+    let since = Datetime::from(Utc::now() - Duration::days(days));
+    let mut res = db
+        .query(
+            "
+            array::len(array::distinct(SELECT VALUE viewer FROM post_views WHERE post = $pid));
+            array::len(SELECT VALUE id FROM follows WHERE source_post = $pid);
+            SELECT time::floor(created_at, 1d) AS day, count() AS count FROM post_views
+                WHERE post = $pid
+                AND created_at >= $since
+                GROUP BY day
+                ORDER BY day;
+            SELECT time::floor(created_at, 1d) AS day, count() AS count FROM reactions
+                WHERE out = $pid
+                AND created_at >= $since
+                GROUP BY day
+                ORDER BY day;
+            ",
+        )
+        .bind(("pid", post.id.clone()))
+        .bind(("since", since))
+        .await?;
+    let unique_viewers = res.take::<Option<usize>>(0)?.unwrap_or(0);
+    let follows = res.take::<Option<usize>>(1)?.unwrap_or(0);
+    let views_by_day = res.take::<Vec<DailyCount>>(2)?;
+    let likes_by_day = res.take::<Vec<DailyCount>>(3)?;
+    Ok(Json(PostInsightsResponse {
+        post: post.id.to_sql(),
+        views: post.views_count,
+        unique_viewers,
+        likes: post.likes_count,
+        comments: post.comments_count,
+        reposts: post.reposts_count,
+        follows,
+        views_by_day: views_by_day.into_iter().map(Into::into).collect(),
+        likes_by_day: likes_by_day.into_iter().map(Into::into).collect(),
+    }))
+}
@@ -0,0 +1,11 @@
+use rocket::{Route, routes};
+
+use crate::insights::insight_service::*;
+
+pub mod insight_service;
+pub mod model;
+pub mod view_service;
+
+pub fn routes() -> Vec<Route> {
+    routes![get_post_insights]
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use surrealdb_types::{Datetime, RecordId, SurrealValue};
+
+/// Where a post was seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, SurrealValue)]
+#[serde(rename_all = "lowercase")]
+#[surreal(untagged, lowercase)]
+pub enum ViewSource {
+    Feed,
+    Post,
+}
+
+/// One impression of a post, counted at most once per viewer and window.
+#[derive(Debug, Clone, Serialize, SurrealValue)]
+pub struct View {
+    pub post: RecordId,
+    pub author: RecordId,
+    pub viewer: RecordId,
+    /// Index of the dedup window the view falls in.
+    pub bucket: i64,
+    pub source: ViewSource,
+    pub created_at: Datetime,
+}
+
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct DailyCount {
+    pub day: Datetime,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DailyCountResponse {
+    pub day: Datetime,
+    pub count: usize,
+}
+
+impl From<DailyCount> for DailyCountResponse {
+    fn from(count: DailyCount) -> Self {
+        Self {
+            day: count.day,
+            count: count.count,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostInsightsResponse {
+    pub post: String,
+    pub views: usize,
+    pub unique_viewers: usize,
+    pub likes: usize,
+    pub comments: usize,
+    pub reposts: usize,
+    /// Accounts that followed the author shortly after seeing this post and
+    /// still follow them.
+    pub follows: usize,
+    /// Per UTC day over the requested period, days without activity left
+    /// out.
+    pub views_by_day: Vec<DailyCountResponse>,
+    pub likes_by_day: Vec<DailyCountResponse>,
+}
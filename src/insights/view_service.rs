@@ -0,0 +1,123 @@
+use std::{collections::HashSet, time::Duration};
+
+use chrono::Utc;
+use rocket::tokio::{
+    self,
+    sync::mpsc::{self, Receiver, Sender, error::TryRecvError},
+};
+use surrealdb_types::{Datetime, RecordId, ToSql};
+
+use crate::{
+    AppResult, DB,
+    db::parse_thing,
+    insights::model::{View, ViewSource},
+    posts::model::{PostKind, PostResponse},
+};
+
+/// Views of the same post by the same viewer within one window count once.
+pub const VIEW_WINDOW_SECS: i64 = 60 * 60;
+/// How often buffered views are written.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+/// Views written per transaction.
+const BATCH_SIZE: usize = 500;
+/// Views buffered before new ones are dropped.
+const QUEUE_CAPACITY: usize = 10_000;
+
+/// Collects post views from request handlers so they can be written in the
+/// background, off the request path.
+pub struct ViewRecorder {
+    tx: Sender<View>,
+}
+
+impl ViewRecorder {
+    /// Returns the recorder together with the receiving end for [`run`].
+    pub fn new() -> (Self, Receiver<View>) {
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        (Self { tx }, rx)
+    }
+
+    /// Queues a view of every post in a response. Reposts count as views
+    /// of their original, and authors viewing their own posts are not
+    /// counted. Views are best-effort: when the queue is full they are
+    /// dropped.
+    pub fn record(&self, viewer: &RecordId, posts: &[PostResponse], source: ViewSource) {
+        let now = Utc::now();
+        let bucket = now.timestamp() / VIEW_WINDOW_SECS;
+        let viewer_id = viewer.to_sql();
+        for response in posts {
+            let (post, author) = match (&response.kind, &response.original) {
+                (PostKind::Repost, Some(original)) => (&original.id, &original.uid),
+                _ => (&response.id, &response.uid),
+            };
+            if *author == viewer_id {
+                continue;
+            }
+            let (Ok(post), Ok(author)) = (parse_thing(post), parse_thing(author)) else {
+                continue;
+            };
+            let _ = self.tx.try_send(View {
+                post,
+                author,
+                viewer: viewer.clone(),
+                bucket,
+                source,
+                created_at: Datetime::from(now),
+            });
+        }
+    }
+}
+
+/// Runs the view writer forever, flushing buffered views every few
+/// seconds.
+pub async fn run(db: DB, mut rx: Receiver<View>) {
+    loop {
+        tokio::time::sleep(FLUSH_INTERVAL).await;
+        let mut batch = Vec::new();
+        let mut seen = HashSet::new();
+        loop {
+            match rx.try_recv() {
+                Ok(view) => {
+                    let key = (view.post.to_sql(), view.viewer.to_sql(), view.bucket);
+                    if seen.insert(key) {
+                        batch.push(view);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        for chunk in batch.chunks(BATCH_SIZE) {
+            if let Err(e) = flush(&db, chunk.to_vec()).await {
+                eprintln!("views: failed to write {} views: {}", chunk.len(), e);
+            }
+        }
+    }
+}
+
+/// Stores the views not yet seen in their window and bumps `views_count`
+/// for each.
+async fn flush(db: &DB, views: Vec<View>) -> AppResult<()> {
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            FOR $view IN $views {
+                LET $post = $view.post;
+                IF record::exists($post) AND (
+                    SELECT id FROM post_views
+                    WHERE post = $post
+                    AND viewer = $view.viewer
+                    AND bucket = $view.bucket
+                ) = [] {
+                    CREATE post_views CONTENT $view;
+                    UPDATE $post SET views_count += 1;
+                };
+            };
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("views", views))
+        .await?;
+    res.check()?;
+    Ok(())
+}
@@ -6,19 +6,31 @@ use std::{
 };
 use surrealdb::{Surreal, engine::remote::ws::Client};
 
-use crate::{error::AppError, ws::WsManager};
+use crate::{
+    error::AppError, insights::view_service::ViewRecorder, media::store::MediaStore, ws::WsManager,
+};
 
+mod bookmarks;
 mod chat;
-mod common_service;
+mod comments;
 mod db;
+mod drafts;
 mod error;
+mod explore;
+mod hashtags;
+mod insights;
 mod jwt;
+mod media;
+mod notifications;
 mod posts;
+mod stories;
 mod users;
 mod ws;
 
 type WS = Arc<WsManager>;
 type DB = Arc<Surreal<Client>>;
+type Store = Arc<dyn MediaStore>;
+type Views = Arc<ViewRecorder>;
 type AppResult<T> = Result<T, AppError>;
 
 #[rocket::main]
@@ -27,23 +39,42 @@ async fn main() -> AppResult<()> {
     dotenvy::dotenv().ok();
     std::fs::create_dir_all("data/profile-pictures").ok();
     std::fs::create_dir_all("data/posts").ok();
-    let db = db::init().await?;
+    let db: DB = Arc::new(db::init().await?);
+    let store = media::store::from_env()?;
+    rocket::tokio::spawn(media::transcode_service::run(db.clone(), store.clone()));
+    rocket::tokio::spawn(media::gc_service::run(db.clone(), store.clone()));
+    rocket::tokio::spawn(stories::sweep_service::run(db.clone(), store.clone()));
+    rocket::tokio::spawn(drafts::scheduler_service::run(db.clone()));
+    rocket::tokio::spawn(explore::trending_service::run(db.clone()));
+    let (views, view_queue) = ViewRecorder::new();
+    rocket::tokio::spawn(insights::view_service::run(db.clone(), view_queue));
 
     rocket::build()
         .configure(rocket::Config {
             address: IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
             port: 8080,
             limits: Limits::new()
-                .limit("file", (20).megabytes())
+                .limit("file", 100.mebibytes())
+                .limit("data-form", 110.mebibytes())
                 .limit("form", 20.megabytes()),
             ..Default::default()
         })
-        .manage(Arc::new(db))
+        .manage(db)
         .manage(Arc::new(WsManager::new()))
+        .manage(store)
+        .manage(Arc::new(views) as Views)
         .mount("/user-service", users::routes())
         .mount("/post-service", posts::routes())
-        .mount("/", rocket::fs::FileServer::from("data"))
         .mount("/chat-service", chat::routes())
+        .mount("/comment-service", comments::routes())
+        .mount("/story-service", stories::routes())
+        .mount("/hashtag-service", hashtags::routes())
+        .mount("/notification-service", notifications::routes())
+        .mount("/bookmark-service", bookmarks::routes())
+        .mount("/draft-service", drafts::routes())
+        .mount("/insight-service", insights::routes())
+        .mount("/explore-service", explore::routes())
+        .mount("/media", media::routes())
         .launch()
         .await?;
     Ok(())
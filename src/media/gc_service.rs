@@ -0,0 +1,91 @@
+use std::{collections::HashSet, time::Duration};
+
+use chrono::Utc;
+use rocket::tokio;
+use surrealdb_types::Datetime;
+
+use crate::{
+    AppResult, DB, Store,
+    media::{image_service, model::MediaBlob, store::MediaStore},
+};
+
+/// How often the collector runs.
+const GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Unreferenced objects younger than this are kept, which covers uploads
+/// whose database row is not written yet and blobs that may be re-uploaded.
+const GC_GRACE: Duration = Duration::from_secs(24 * 60 * 60);
+/// Key prefixes owned by the upload pipelines.
+const MEDIA_ROOTS: [&str; 3] = ["posts/", "profile-pictures/", "stories/"];
+
+/// Runs the media garbage collector forever.
+pub async fn run(db: DB, store: Store) {
+    loop {
+        if let Err(e) = collect(&db, store.as_ref()).await {
+            eprintln!("media gc: {}", e);
+        }
+        tokio::time::sleep(GC_INTERVAL).await;
+    }
+}
+
+/// Drops blobs that have been unreferenced for longer than the grace period,
+/// then deletes every stored object under the media roots that no user,
+/// post, story, pending transcode or live blob points at.
+///
+/// A blob is marked `collecting` before its files are removed and only
+/// deleted afterwards; uploads of the same bytes are refused while the mark
+/// is set, so they cannot write files that are about to be removed.
+async fn collect(db: &DB, store: &dyn MediaStore) -> AppResult<()> {
+    let cutoff = Utc::now() - GC_GRACE;
+    let expired: Vec<MediaBlob> = db
+        .query(
+            "
+            UPDATE media_blobs SET collecting = true
+                WHERE refs <= 0 AND updated_at < $cutoff
+                RETURN AFTER;
+            ",
+        )
+        .bind(("cutoff", Datetime::from(cutoff)))
+        .await?
+        .take(0)?;
+    for blob in expired {
+        image_service::remove_renditions(store, &blob.renditions).await?;
+        db.query("DELETE $blob")
+            .bind(("blob", blob.id))
+            .await?
+            .check()?;
+    }
+
+    let referenced: HashSet<String> = referenced_keys(db).await?.into_iter().collect();
+    for root in MEDIA_ROOTS {
+        for object in store.list(root).await? {
+            if object.modified < cutoff && !referenced.contains(&object.key) {
+                store.delete(&object.key).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn referenced_keys(db: &DB) -> AppResult<Vec<String>> {
+    let keys: Vec<Option<String>> = db
+        .query(
+            "
+            LET $users = SELECT VALUE array::concat(
+                [profile_picture],
+                (profile_picture_renditions ?? []).storage_key
+            ) FROM users;
+            LET $posts = SELECT VALUE array::concat(
+                (media ?? []).storage_key,
+                array::flatten((media ?? []).renditions ?? []).storage_key,
+                array::flatten((media ?? []).poster ?? []).storage_key
+            ) FROM posts, stories, drafts;
+            LET $sources = SELECT VALUE source_key FROM transcode_jobs
+                WHERE status IN ['queued', 'running'];
+            LET $blobs = SELECT VALUE renditions.storage_key FROM media_blobs;
+            RETURN array::flatten(array::concat($users, $posts, [$sources], $blobs));
+            ",
+        )
+        .await?
+        .take(4)?;
+    Ok(keys.into_iter().flatten().collect())
+}
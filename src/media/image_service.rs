@@ -0,0 +1,235 @@
+use std::io::Cursor;
+
+use image::{
+    DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits, codecs::jpeg::JpegEncoder,
+    imageops::FilterType,
+};
+use rocket::{fs::TempFile, tokio::io::AsyncReadExt};
+use sha2::{Digest, Sha256};
+use surrealdb_types::RecordId;
+use validator::ValidationError;
+
+use crate::{
+    AppResult, DB,
+    error::AppError,
+    media::{
+        model::{MediaBlob, Placeholder, Rendition, RenditionSize, StoredImage},
+        placeholder,
+        store::MediaStore,
+    },
+};
+
+/// Upper bound on the size of an uploaded image.
+pub const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+const MAX_SOURCE_EDGE: u32 = 12_000;
+const JPEG_QUALITY: u8 = 85;
+
+/// A rendition encoded in memory, not yet written anywhere.
+struct EncodedRendition {
+    size: RenditionSize,
+    bytes: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+/// Decodes an uploaded image, normalizes it and writes its renditions to
+/// `store` under `<dir>/`.
+pub async fn store_image(
+    db: &DB,
+    store: &dyn MediaStore,
+    file: &TempFile<'_>,
+    dir: &str,
+) -> AppResult<StoredImage> {
+    store_image_bytes(db, store, read_upload(file).await?, dir).await
+}
+
+/// Like [`store_image`], for an image that is already in memory.
+///
+/// Renditions are content-addressed by the SHA-256 of the upload, so
+/// uploading the same bytes again into `dir` reuses the stored renditions
+/// and only takes another reference on their `media_blobs` row.
+pub async fn store_image_bytes(
+    db: &DB,
+    store: &dyn MediaStore,
+    bytes: Vec<u8>,
+    dir: &str,
+) -> AppResult<StoredImage> {
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Images must be at most 20 MB",
+        )));
+    }
+    let base = format!("{}/{}", dir, hex::encode(Sha256::digest(&bytes)));
+    let blob = RecordId::new("media_blobs", base.as_str());
+    let mut res = db
+        .query(
+            "
+            UPDATE $blob SET refs += 1, updated_at = time::now()
+                WHERE collecting != true
+                RETURN AFTER;
+            SELECT VALUE collecting FROM ONLY $blob;
+            ",
+        )
+        .bind(("blob", blob.clone()))
+        .await?;
+    if let Some(existing) = res.take::<Vec<MediaBlob>>(0)?.into_iter().next() {
+        return Ok(StoredImage {
+            renditions: existing.renditions,
+            placeholder: existing.placeholder,
+        });
+    }
+    // The GC is deleting this blob's files; writing them again now could
+    // have them deleted right after.
+    if res.take::<Option<bool>>(1)?.unwrap_or(false) {
+        return Err(AppError::XCustomMessage(
+            "This image is being cleaned up, try again shortly",
+        ));
+    }
+
+    let (renditions, placeholder) =
+        rocket::tokio::task::spawn_blocking(move || process_image(&bytes))
+            .await
+            .map_err(|_| AppError::XCustomMessage("Image processing failed"))??;
+    let mut stored = Vec::with_capacity(renditions.len());
+    for rendition in renditions {
+        let storage_key = format!("{}_{}.jpg", base, rendition.size.as_str());
+        if let Err(e) = store.put(&storage_key, rendition.bytes, "image/jpeg").await {
+            remove_renditions(store, &stored).await?;
+            return Err(e);
+        }
+        stored.push(Rendition {
+            size: rendition.size,
+            storage_key,
+            width: rendition.width,
+            height: rendition.height,
+        });
+    }
+    // Concurrent uploads of the same bytes write identical objects, so
+    // both simply add their reference.
+    db.query(
+        "
+        UPSERT $blob SET
+            renditions = $renditions,
+            placeholder = $placeholder,
+            refs = (refs ?? 0) + 1,
+            updated_at = time::now();
+        ",
+    )
+    .bind(("blob", blob))
+    .bind(("renditions", stored.clone()))
+    .bind(("placeholder", placeholder.clone()))
+    .await?
+    .check()?;
+    Ok(StoredImage {
+        renditions: stored,
+        placeholder: Some(placeholder),
+    })
+}
+
+/// Drops one reference on the blobs behind `renditions`. Nothing is deleted
+/// here; unreferenced blobs are collected by the media GC after a grace
+/// period. Renditions that predate content addressing have no blob row and
+/// are left to the GC's orphan scan.
+pub async fn release(db: &DB, renditions: &[Rendition]) -> AppResult<()> {
+    let blobs: Vec<RecordId> = renditions
+        .iter()
+        .filter_map(|r| blob_of(&r.storage_key))
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .map(|base| RecordId::new("media_blobs", base))
+        .collect();
+    if blobs.is_empty() {
+        return Ok(());
+    }
+    db.query("UPDATE $blobs SET refs -= 1, updated_at = time::now()")
+        .bind(("blobs", blobs))
+        .await?
+        .check()?;
+    Ok(())
+}
+
+/// `<dir>/<sha256>_<size>.jpg` -> `<dir>/<sha256>`.
+fn blob_of(storage_key: &str) -> Option<String> {
+    let (base, _) = storage_key.rsplit_once('_')?;
+    let (_, hash) = base.rsplit_once('/')?;
+    (hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())).then(|| base.to_string())
+}
+
+pub async fn remove_renditions(store: &dyn MediaStore, renditions: &[Rendition]) -> AppResult<()> {
+    for rendition in renditions {
+        store.delete(&rendition.storage_key).await?;
+    }
+    Ok(())
+}
+
+pub async fn read_upload(file: &TempFile<'_>) -> AppResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    file.open().await?.read_to_end(&mut bytes).await?;
+    Ok(bytes)
+}
+
+/// Sniffs the real format from magic bytes, decodes with size limits, applies
+/// EXIF orientation and re-encodes every rendition as baseline JPEG. The
+/// re-encode drops all source metadata, including EXIF/GPS blocks.
+fn process_image(bytes: &[u8]) -> AppResult<(Vec<EncodedRendition>, Placeholder)> {
+    let format = image::guess_format(bytes).map_err(|_| invalid_image())?;
+    if !matches!(
+        format,
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP
+    ) {
+        return Err(invalid_image());
+    }
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_EDGE);
+    limits.max_image_height = Some(MAX_SOURCE_EDGE);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().map_err(|_| invalid_image())?;
+    let orientation = decoder.orientation().map_err(|_| invalid_image())?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|_| invalid_image())?;
+    image.apply_orientation(orientation);
+    let image = flatten(image);
+    let placeholder = placeholder::compute(&image);
+
+    let renditions = RenditionSize::ALL
+        .iter()
+        .map(|&size| {
+            let resized = if image.width().max(image.height()) > size.max_edge() {
+                image.resize(size.max_edge(), size.max_edge(), FilterType::Lanczos3)
+            } else {
+                image.clone()
+            };
+            let mut bytes = Vec::new();
+            JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY)
+                .encode_image(&resized)
+                .map_err(|_| AppError::XCustomMessage("Image encoding failed"))?;
+            Ok(EncodedRendition {
+                size,
+                bytes,
+                width: resized.width(),
+                height: resized.height(),
+            })
+        })
+        .collect::<AppResult<_>>()?;
+    Ok((renditions, placeholder))
+}
+
+/// Composites any alpha channel onto white, since JPEG has no transparency.
+fn flatten(image: DynamicImage) -> DynamicImage {
+    if !image.color().has_alpha() {
+        return DynamicImage::ImageRgb8(image.to_rgb8());
+    }
+    let rgba = image.to_rgba8();
+    let rgb = image::RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        image::Rgb([blend(r), blend(g), blend(b)])
+    });
+    DynamicImage::ImageRgb8(rgb)
+}
+
+fn invalid_image() -> AppError {
+    AppError::ValidationError(ValidationError::new(
+        "File must be a PNG, JPEG or WebP image",
+    ))
+}
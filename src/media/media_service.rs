@@ -0,0 +1,173 @@
+use std::{path::PathBuf, time::Duration};
+
+use rocket::{State, get, http::ContentType};
+use serde_json::{Value, json};
+
+use surrealdb_types::RecordId;
+
+use crate::{
+    AppResult, DB, Store,
+    db::parse_thing,
+    error::AppError,
+    jwt::AuthUser,
+    media::urls,
+    posts::{enrich_service, model::Post},
+    stories::{model::Story, story_service},
+};
+
+/// Lifetime of the URLs handed out by [`presign_media`].
+const PRESIGN_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Streams an object from the configured store, so media can be served
+/// through the API regardless of where it lives. When URL signing is
+/// enabled, media of private accounts and of non-public posts is only
+/// served with a valid `expires`/`signature` pair.
+#[get("/<key..>?<expires>&<signature>")]
+pub async fn get_media(
+    key: PathBuf,
+    expires: Option<u64>,
+    signature: Option<&str>,
+    store: &State<Store>,
+    db: &State<DB>,
+) -> AppResult<(ContentType, Vec<u8>)> {
+    let content_type = key
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(ContentType::from_extension)
+        .unwrap_or(ContentType::Binary);
+    let key = key
+        .to_str()
+        .ok_or(AppError::XCustomMessage("Invalid storage key"))?;
+    let signed = match (expires, signature) {
+        (Some(expires), Some(signature)) => urls::verify(key, expires, signature),
+        _ => false,
+    };
+    if !signed && urls::signing_enabled() && requires_signature(db, key).await? {
+        return Err(AppError::Forbidden("This media requires a signed URL"));
+    }
+    let bytes = store
+        .get(key)
+        .await?
+        .ok_or(AppError::XCustomMessage("Media not found"))?;
+    Ok((content_type, bytes))
+}
+
+/// Issues a short-lived direct URL for `key`. Media that needs a signed URL
+/// is only presigned for callers who may see a post or story carrying it.
+#[get("/presign/<key..>")]
+pub async fn presign_media(
+    key: PathBuf,
+    store: &State<Store>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Value> {
+    let key = key
+        .to_str()
+        .ok_or(AppError::XCustomMessage("Invalid storage key"))?;
+    if urls::signing_enabled() && requires_signature(db, key).await? {
+        ensure_visible(db, &parse_thing(&auth.user_id)?, key).await?;
+    }
+    let url = store.presign(key, PRESIGN_TTL).await?;
+    Ok(json!({
+        "url": url,
+        "expires_in": PRESIGN_TTL.as_secs()
+    }))
+}
+
+/// Matches records with `$key` among their media, renditions or posters,
+/// through the indexed `media_keys` field.
+const CARRIES_KEY: &str = "media_keys CONTAINS $key";
+
+/// Post and story media keys are laid out as `<posts|stories>/<user
+/// key>/<file>`; legacy post media as `posts/<file>`, with no owner in the
+/// key. Anything else (profile pictures) is public.
+fn media_owner(key: &str) -> Option<(&str, Option<RecordId>)> {
+    match key.split('/').collect::<Vec<_>>().as_slice() {
+        [root @ ("posts" | "stories"), owner, _, ..] => {
+            Some((root, Some(RecordId::new("users", *owner))))
+        }
+        ["posts", _] => Some(("posts", None)),
+        _ => None,
+    }
+}
+
+/// Post and story media is restricted when its owner is private, and post
+/// media also when any post carrying it is not public or archived, or it
+/// belongs to an unpublished draft. Legacy keys are resolved through the
+/// posts carrying them.
+async fn requires_signature(db: &DB, key: &str) -> AppResult<bool> {
+    let Some((root, owner)) = media_owner(key) else {
+        return Ok(false);
+    };
+    let mut res = db
+        .query(format!(
+            "
+            LET $carriers = IF $posts THEN (
+                SELECT id, uid, visibility, archived FROM posts, drafts
+                    WHERE {CARRIES_KEY}
+                    AND ($owner = NONE OR uid = $owner)
+            ) ELSE [] END;
+            LET $owners = IF $owner = NONE THEN array::distinct($carriers.uid) ELSE [$owner] END;
+            RETURN (SELECT VALUE id FROM $owners WHERE is_private = true) != []
+                OR (
+                    SELECT VALUE id FROM $carriers
+                    WHERE record::tb(id) = 'drafts'
+                    OR (visibility ?? 'public') != 'public'
+                    OR archived = true
+                ) != [];
+            "
+        ))
+        .bind(("owner", owner))
+        .bind(("posts", root == "posts"))
+        .bind(("key", key.to_string()))
+        .await?;
+    Ok(res.take::<Option<bool>>(2)?.unwrap_or(false))
+}
+
+/// Restricted media is visible to its owner, and otherwise to whoever may
+/// see a post or live story carrying it.
+async fn ensure_visible(db: &DB, viewer: &RecordId, key: &str) -> AppResult<()> {
+    let Some((root, owner)) = media_owner(key) else {
+        return Ok(());
+    };
+    if owner.as_ref() == Some(viewer) {
+        return Ok(());
+    }
+    if root == "stories" {
+        let stories: Vec<Story> = db
+            .query(format!(
+                "SELECT * FROM stories WHERE {CARRIES_KEY} AND uid = $owner AND expires_at > time::now()"
+            ))
+            .bind(("owner", owner))
+            .bind(("key", key.to_string()))
+            .await?
+            .take(0)?;
+        for story in &stories {
+            if story_service::ensure_visible(db, viewer, story)
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    } else {
+        let posts: Vec<Post> = db
+            .query(format!(
+                "SELECT * FROM posts WHERE {CARRIES_KEY} AND ($owner = NONE OR uid = $owner)"
+            ))
+            .bind(("owner", owner))
+            .bind(("key", key.to_string()))
+            .await?
+            .take(0)?;
+        for post in &posts {
+            if post.uid == *viewer
+                || enrich_service::ensure_visible(db, viewer, post)
+                    .await
+                    .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    }
+    Err(AppError::XCustomMessage("Media not found"))
+}
@@ -0,0 +1,17 @@
+use rocket::{Route, routes};
+
+use crate::media::media_service::*;
+
+pub mod gc_service;
+pub mod image_service;
+pub mod media_service;
+pub mod model;
+pub mod placeholder;
+pub mod store;
+pub mod transcode_service;
+pub mod urls;
+pub mod video_service;
+
+pub fn routes() -> Vec<Route> {
+    routes![get_media, presign_media]
+}
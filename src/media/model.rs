@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use surrealdb_types::{Datetime, RecordId, SurrealValue};
+
+use crate::media::urls;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, SurrealValue)]
+#[serde(rename_all = "lowercase")]
+#[surreal(untagged, lowercase)]
+pub enum RenditionSize {
+    Thumbnail,
+    Medium,
+    Full,
+}
+
+impl RenditionSize {
+    pub const ALL: [RenditionSize; 3] = [
+        RenditionSize::Thumbnail,
+        RenditionSize::Medium,
+        RenditionSize::Full,
+    ];
+
+    /// Longest edge in pixels; images are never upscaled past their source.
+    pub fn max_edge(self) -> u32 {
+        match self {
+            RenditionSize::Thumbnail => 320,
+            RenditionSize::Medium => 720,
+            RenditionSize::Full => 1440,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RenditionSize::Thumbnail => "thumbnail",
+            RenditionSize::Medium => "medium",
+            RenditionSize::Full => "full",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SurrealValue)]
+pub struct Rendition {
+    pub size: RenditionSize,
+    pub storage_key: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenditionResponse {
+    pub size: RenditionSize,
+    pub storage_key: String,
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl From<Rendition> for RenditionResponse {
+    fn from(rendition: Rendition) -> Self {
+        Self {
+            url: urls::public_url(&rendition.storage_key),
+            size: rendition.size,
+            storage_key: rendition.storage_key,
+            width: rendition.width,
+            height: rendition.height,
+        }
+    }
+}
+
+impl RenditionResponse {
+    /// Swaps the public URL for an expiring signed one.
+    pub fn sign(&mut self) {
+        self.url = urls::signed_url(&self.storage_key, urls::SIGNED_URL_TTL);
+    }
+}
+
+/// What clients draw while an image loads.
+#[derive(Debug, Clone, Serialize, Deserialize, SurrealValue)]
+pub struct Placeholder {
+    pub blurhash: String,
+    /// `#rrggbb`.
+    pub dominant_color: String,
+    /// Width divided by height.
+    pub aspect_ratio: f64,
+}
+
+/// An uploaded image after processing: every rendition has been written to
+/// storage.
+#[derive(Debug, Clone)]
+pub struct StoredImage {
+    pub renditions: Vec<Rendition>,
+    /// `None` only for blobs stored before placeholders existed.
+    pub placeholder: Option<Placeholder>,
+}
+
+impl StoredImage {
+    pub fn full(&self) -> &Rendition {
+        self.renditions
+            .iter()
+            .find(|r| r.size == RenditionSize::Full)
+            .unwrap_or(&self.renditions[0])
+    }
+}
+
+/// Reference-counted set of renditions for one content hash, stored at
+/// `media_blobs:⟨<dir>/<sha256>⟩`.
+#[derive(Debug, Clone, Deserialize, SurrealValue)]
+pub struct MediaBlob {
+    pub id: RecordId,
+    pub renditions: Vec<Rendition>,
+    #[surreal(default)]
+    pub placeholder: Option<Placeholder>,
+}
+
+/// Lifecycle of a row in `transcode_jobs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, SurrealValue)]
+#[serde(rename_all = "lowercase")]
+#[surreal(untagged, lowercase)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// A queued transcode of the video at `position` in `post`'s media. `post`
+/// may also be a story record.
+#[derive(Debug, Clone, Serialize, Deserialize, SurrealValue)]
+pub struct TranscodeJob {
+    pub id: RecordId,
+    pub post: RecordId,
+    pub position: usize,
+    pub source_key: String,
+    pub status: JobStatus,
+    pub attempts: u32,
+}
+
+#[derive(Debug, Serialize, SurrealValue)]
+pub struct TranscodeJobRequest {
+    pub post: RecordId,
+    pub position: usize,
+    pub source_key: String,
+    pub status: JobStatus,
+    pub attempts: u32,
+    pub created_at: Datetime,
+}
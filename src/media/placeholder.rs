@@ -0,0 +1,185 @@
+use std::f64::consts::PI;
+
+use image::{DynamicImage, RgbImage, imageops::FilterType};
+
+use crate::media::model::Placeholder;
+
+/// BlurHash components along x and y.
+const COMPONENTS_X: usize = 4;
+const COMPONENTS_Y: usize = 3;
+/// Images are shrunk to this longest edge before sampling; a BlurHash only
+/// keeps a handful of frequencies, so more pixels buy nothing.
+const SAMPLE_EDGE: u32 = 32;
+
+const BASE83: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[\\]^_{|}~";
+
+/// Computes the loading placeholder for an already oriented, flattened image.
+pub fn compute(image: &DynamicImage) -> Placeholder {
+    let sample = image
+        .resize(SAMPLE_EDGE, SAMPLE_EDGE, FilterType::Triangle)
+        .to_rgb8();
+    Placeholder {
+        blurhash: blurhash(&sample),
+        dominant_color: dominant_color(&sample),
+        aspect_ratio: image.width() as f64 / image.height().max(1) as f64,
+    }
+}
+
+/// Encodes `image` as a BlurHash (https://blurha.sh).
+fn blurhash(image: &RgbImage) -> String {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut factors = Vec::with_capacity(COMPONENTS_X * COMPONENTS_Y);
+    for j in 0..COMPONENTS_Y {
+        for i in 0..COMPONENTS_X {
+            let normalization = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0; 3];
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let basis = (PI * i as f64 * x as f64 / width as f64).cos()
+                    * (PI * j as f64 * y as f64 / height as f64).cos();
+                for (channel, value) in factor.iter_mut().zip(pixel.0) {
+                    *channel += basis * srgb_to_linear(value);
+                }
+            }
+            let scale = normalization / (width * height) as f64;
+            factors.push(factor.map(|c| c * scale));
+        }
+    }
+
+    let (dc, ac) = factors.split_first().expect("at least one component");
+    let mut hash = String::with_capacity(4 + 2 * factors.len());
+    encode83(
+        ((COMPONENTS_X - 1) + (COMPONENTS_Y - 1) * 9) as u32,
+        1,
+        &mut hash,
+    );
+    let max_value = if ac.is_empty() {
+        encode83(0, 1, &mut hash);
+        1.0
+    } else {
+        let actual_max = ac
+            .iter()
+            .flat_map(|f| f.iter())
+            .fold(0.0_f64, |max, c| max.max(c.abs()));
+        let quantised = (actual_max * 166.0 - 0.5).floor().clamp(0.0, 82.0) as u32;
+        encode83(quantised, 1, &mut hash);
+        (quantised + 1) as f64 / 166.0
+    };
+    let dc_value =
+        (linear_to_srgb(dc[0]) << 16) + (linear_to_srgb(dc[1]) << 8) + linear_to_srgb(dc[2]);
+    encode83(dc_value, 4, &mut hash);
+    for factor in ac {
+        let quantise = |c: f64| {
+            (sign_pow(c / max_value, 0.5) * 9.0 + 9.5)
+                .floor()
+                .clamp(0.0, 18.0) as u32
+        };
+        let value = quantise(factor[0]) * 19 * 19 + quantise(factor[1]) * 19 + quantise(factor[2]);
+        encode83(value, 2, &mut hash);
+    }
+    hash
+}
+
+/// The most common colour after quantizing to 4 bits per channel, reported
+/// as the mean of that bucket so it stays close to the real pixels.
+fn dominant_color(image: &RgbImage) -> String {
+    let mut buckets = vec![(0_u32, [0_u64; 3]); 4096];
+    for pixel in image.pixels() {
+        let [r, g, b] = pixel.0;
+        let index = ((r as usize >> 4) << 8) | ((g as usize >> 4) << 4) | (b as usize >> 4);
+        let bucket = &mut buckets[index];
+        bucket.0 += 1;
+        for (sum, value) in bucket.1.iter_mut().zip(pixel.0) {
+            *sum += value as u64;
+        }
+    }
+    let (count, sums) = buckets
+        .into_iter()
+        .max_by_key(|(count, _)| *count)
+        .unwrap_or_default();
+    let [r, g, b] = sums.map(|sum| (sum / count.max(1) as u64) as u8);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn encode83(value: u32, length: u32, out: &mut String) {
+    for i in 1..=length {
+        let digit = (value / 83_u32.pow(length - i)) % 83;
+        out.push(BASE83[digit as usize] as char);
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let v = value as f64 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> u32 {
+    let v = value.clamp(0.0, 1.0);
+    let srgb = if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0 + 0.5) as u32
+}
+
+fn sign_pow(value: f64, exp: f64) -> f64 {
+    value.abs().powf(exp).copysign(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+
+    fn decode83(hash: &str) -> u32 {
+        hash.bytes().fold(0, |value, byte| {
+            let digit = BASE83.iter().position(|&b| b == byte).unwrap() as u32;
+            value * 83 + digit
+        })
+    }
+
+    // Expected hashes follow the reference encoder (woltapp/blurhash) with
+    // 4x3 components.
+    #[test]
+    fn blurhash_matches_reference_encoder() {
+        let black = RgbImage::from_pixel(4, 3, Rgb([0, 0, 0]));
+        assert_eq!(blurhash(&black), "L00000fQfQfQfQfQfQfQfQfQfQfQ");
+
+        let gradient = RgbImage::from_fn(8, 6, |x, y| Rgb([(x * 32) as u8, (y * 40) as u8, 128]));
+        assert_eq!(blurhash(&gradient), "LjF=ad3Ba{xuzONLfQnTeqf7fQf7");
+
+        let split = RgbImage::from_fn(8, 8, |x, _| {
+            if x < 4 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        });
+        assert_eq!(blurhash(&split), "L}LjfL{T,SST$Awun}b0fQfQfQfQ");
+    }
+
+    #[test]
+    fn encode83_round_trips() {
+        for (value, length) in [(0, 1), (21, 1), (82, 1), (3429, 2), (16_777_215, 4)] {
+            let mut out = String::new();
+            encode83(value, length, &mut out);
+            assert_eq!(out.len(), length as usize);
+            assert_eq!(decode83(&out), value);
+        }
+    }
+
+    #[test]
+    fn compute_reports_colour_and_aspect_ratio() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 32, Rgb([12, 200, 99])));
+        let placeholder = compute(&image);
+        assert_eq!(placeholder.dominant_color, "#0cc863");
+        assert_eq!(placeholder.aspect_ratio, 2.0);
+        assert_eq!(placeholder.blurhash.len(), 28);
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    env,
+    path::Path,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use rocket::tokio;
+use surrealdb_types::{Datetime, RecordId, ToSql};
+use uuid::Uuid;
+
+use crate::{
+    AppResult, DB, Store,
+    error::AppError,
+    media::{
+        image_service,
+        model::{
+            JobStatus, Rendition, RenditionSize, StoredImage, TranscodeJob, TranscodeJobRequest,
+        },
+        store::MediaStore,
+    },
+    notifications::notification_service,
+    posts::{
+        model::{Media, MediaKind, PostStatus},
+        post_service, timeline_service,
+    },
+};
+
+/// How long the worker sleeps when the queue is empty.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How often a missing ffmpeg binary is looked for again.
+const FFMPEG_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// A job is retried until it has been attempted this many times.
+const MAX_ATTEMPTS: u32 = 3;
+/// Video renditions produced by ffmpeg; thumbnails only exist for posters.
+const VIDEO_RENDITIONS: [RenditionSize; 2] = [RenditionSize::Medium, RenditionSize::Full];
+
+/// ffmpeg binary, `FFMPEG_PATH` or `ffmpeg` from `PATH`.
+static FFMPEG: Lazy<String> =
+    Lazy::new(|| env::var("FFMPEG_PATH").unwrap_or_else(|_| "ffmpeg".to_string()));
+
+/// Encoded outputs of one transcode, not yet written anywhere.
+struct Transcoded {
+    poster: Vec<u8>,
+    renditions: Vec<(RenditionSize, Vec<u8>)>,
+}
+
+/// Queues a transcode job for every video in a freshly created post or
+/// story, identified by `record`.
+pub async fn enqueue(db: &DB, record: &RecordId, media: &[Media]) -> AppResult<()> {
+    let jobs: Vec<TranscodeJobRequest> = media
+        .iter()
+        .filter(|m| m.kind == MediaKind::Video)
+        .map(|m| TranscodeJobRequest {
+            post: record.clone(),
+            position: m.position,
+            source_key: m.storage_key.clone(),
+            status: JobStatus::Queued,
+            attempts: 0,
+            created_at: Datetime::now(),
+        })
+        .collect();
+    if jobs.is_empty() {
+        return Ok(());
+    }
+    db.query("INSERT INTO transcode_jobs $jobs")
+        .bind(("jobs", jobs))
+        .await?
+        .check()?;
+    Ok(())
+}
+
+/// Runs the transcode worker forever. Jobs left `running` by a previous
+/// process are put back in the queue first. Without a working ffmpeg no
+/// job is taken, so videos stay queued until the binary shows up.
+pub async fn run(db: DB, store: Store) {
+    if let Err(e) = requeue_running(&db).await {
+        eprintln!("transcode: failed to requeue jobs: {}", e);
+    }
+    let mut warned = false;
+    while !tokio::task::spawn_blocking(ffmpeg_available)
+        .await
+        .unwrap_or(false)
+    {
+        if !warned {
+            eprintln!(
+                "transcode: ffmpeg not found at `{}`; videos stay queued until it is installed",
+                FFMPEG.as_str()
+            );
+            warned = true;
+        }
+        tokio::time::sleep(FFMPEG_RETRY_INTERVAL).await;
+    }
+    loop {
+        let job = match claim_next(&db).await {
+            Ok(Some(job)) => job,
+            Ok(None) => {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("transcode: failed to claim a job: {}", e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
+            }
+        };
+        let result = process(&db, store.as_ref(), &job).await;
+        if let Err(e) = finish(&db, &job, result).await {
+            eprintln!("transcode: failed to finish {}: {}", job.id.to_sql(), e);
+        }
+    }
+}
+
+fn ffmpeg_available() -> bool {
+    Command::new(FFMPEG.as_str())
+        .arg("-version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+async fn requeue_running(db: &DB) -> AppResult<()> {
+    db.query("UPDATE transcode_jobs SET status = 'queued' WHERE status = 'running'")
+        .await?
+        .check()?;
+    Ok(())
+}
+
+async fn claim_next(db: &DB) -> AppResult<Option<TranscodeJob>> {
+    let job = db
+        .query(
+            "
+            LET $next = (SELECT VALUE id FROM transcode_jobs
+                WHERE status = 'queued'
+                ORDER BY created_at
+                LIMIT 1)[0];
+            IF $next != NONE {
+                UPDATE ONLY $next SET
+                    status = 'running',
+                    attempts += 1,
+                    updated_at = time::now()
+                RETURN AFTER
+            };
+            ",
+        )
+        .await?
+        .take::<Option<TranscodeJob>>(1)?;
+    Ok(job)
+}
+
+/// Transcodes one video and writes the result into the post or story that
+/// holds it. If that record was deleted in the meantime the new files are
+/// cleaned up again.
+async fn process(db: &DB, store: &dyn MediaStore, job: &TranscodeJob) -> AppResult<()> {
+    let source = store
+        .get(&job.source_key)
+        .await?
+        .ok_or(AppError::XCustomMessage("Source video not found"))?;
+    let (dir, file) = job
+        .source_key
+        .rsplit_once('/')
+        .ok_or(AppError::XCustomMessage("Invalid storage key"))?;
+    let extension = Path::new(file)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("mp4")
+        .to_string();
+    let transcoded = tokio::task::spawn_blocking(move || transcode(&source, &extension))
+        .await
+        .map_err(|_| AppError::XCustomMessage("Video processing failed"))??;
+
+    let poster = image_service::store_image_bytes(db, store, transcoded.poster, dir).await?;
+    let renditions = match store_renditions(store, dir, &poster, transcoded.renditions).await {
+        Ok(renditions) => renditions,
+        Err(e) => {
+            image_service::release(db, &poster.renditions).await?;
+            return Err(e);
+        }
+    };
+
+    let media = db
+        .query("SELECT VALUE media FROM ONLY $post")
+        .bind(("post", job.post.clone()))
+        .await?
+        .take::<Option<Vec<Media>>>(0)?;
+    let Some(mut media) = media else {
+        image_service::release(db, &poster.renditions).await?;
+        image_service::remove_renditions(store, &renditions).await?;
+        return Ok(());
+    };
+    if let Some(item) = media.iter_mut().find(|m| m.position == job.position) {
+        let full = renditions
+            .iter()
+            .find(|r| r.size == RenditionSize::Full)
+            .unwrap_or(&renditions[0]);
+        *item = Media {
+            storage_key: full.storage_key.clone(),
+            width: Some(full.width),
+            height: Some(full.height),
+            renditions,
+            poster: poster.renditions,
+            placeholder: poster.placeholder,
+            ..item.clone()
+        };
+    }
+    db.query("UPDATE $post SET media = $media")
+        .bind(("post", job.post.clone()))
+        .bind(("media", media))
+        .await?
+        .check()?;
+    store.delete(&job.source_key).await?;
+    Ok(())
+}
+
+/// Writes the encoded videos under `<dir>/`. Dimensions are taken from the
+/// poster rendition of the same size, since both are scaled the same way.
+async fn store_renditions(
+    store: &dyn MediaStore,
+    dir: &str,
+    poster: &StoredImage,
+    encoded: Vec<(RenditionSize, Vec<u8>)>,
+) -> AppResult<Vec<Rendition>> {
+    let id = Uuid::new_v4();
+    let mut stored = Vec::with_capacity(encoded.len());
+    for (size, bytes) in encoded {
+        let storage_key = format!("{}/{}_{}.mp4", dir, id, size.as_str());
+        if let Err(e) = store.put(&storage_key, bytes, "video/mp4").await {
+            image_service::remove_renditions(store, &stored).await?;
+            return Err(e);
+        }
+        let frame = poster
+            .renditions
+            .iter()
+            .find(|r| r.size == size)
+            .unwrap_or(poster.full());
+        stored.push(Rendition {
+            size,
+            storage_key,
+            width: frame.width,
+            height: frame.height,
+        });
+    }
+    Ok(stored)
+}
+
+/// Records the outcome of a job. Failed jobs are retried up to
+/// [`MAX_ATTEMPTS`]; once no job of the record is pending, it becomes
+/// `ready` or `failed`. Ready posts are fanned out to followers.
+async fn finish(db: &DB, job: &TranscodeJob, result: AppResult<()>) -> AppResult<()> {
+    let (status, error) = match result {
+        Ok(()) => (JobStatus::Done, None),
+        Err(e) if job.attempts < MAX_ATTEMPTS => (JobStatus::Queued, Some(e.to_string())),
+        Err(e) => (JobStatus::Failed, Some(e.to_string())),
+    };
+    let mut res = db
+        .query(
+            "
+            UPDATE $job SET status = $status, error = $error, updated_at = time::now();
+            LET $pending = SELECT VALUE id FROM transcode_jobs
+                WHERE post = $post AND status IN ['queued', 'running'];
+            LET $failed = SELECT VALUE id FROM transcode_jobs
+                WHERE post = $post AND status = 'failed';
+            IF array::len($pending) = 0 {
+                UPDATE $post SET
+                    status = IF array::len($failed) > 0 { 'failed' } ELSE { 'ready' }
+                RETURN VALUE status
+            };
+            ",
+        )
+        .bind(("job", job.id.clone()))
+        .bind(("post", job.post.clone()))
+        .bind(("status", status))
+        .bind(("error", error))
+        .await?;
+    let status = res.take::<Vec<PostStatus>>(3)?.into_iter().next();
+    if status == Some(PostStatus::Ready) && job.post.table.as_str() == "posts" {
+        let post = post_service::find_post(db, job.post.clone()).await?;
+        timeline_service::fan_out_post(db, &post).await?;
+        notification_service::sync_mentions(db, &post.uid, &post, None, &post.mentions).await?;
+    }
+    Ok(())
+}
+
+/// Extracts a poster frame and encodes the H.264/AAC renditions in a
+/// scratch directory, which is removed afterwards.
+fn transcode(source: &[u8], extension: &str) -> AppResult<Transcoded> {
+    let dir = env::temp_dir().join(format!("transcode-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)?;
+    let result = run_ffmpeg(&dir, source, extension);
+    std::fs::remove_dir_all(&dir).ok();
+    result
+}
+
+fn run_ffmpeg(dir: &Path, source: &[u8], extension: &str) -> AppResult<Transcoded> {
+    let input = dir.join(format!("source.{}", extension));
+    std::fs::write(&input, source)?;
+
+    let poster = dir.join("poster.png");
+    execute(
+        ffmpeg()
+            .arg("-i")
+            .arg(&input)
+            .args(["-vf", "thumbnail", "-frames:v", "1"])
+            .arg(&poster),
+    )?;
+
+    let mut renditions = Vec::with_capacity(VIDEO_RENDITIONS.len());
+    for size in VIDEO_RENDITIONS {
+        let output = dir.join(format!("{}.mp4", size.as_str()));
+        let edge = size.max_edge();
+        // Fit the longest edge, never upscale, keep both sides even for x264.
+        let scale = format!(
+            "scale='if(gte(iw,ih),trunc(min({e},iw)/2)*2,-2)':'if(gte(iw,ih),-2,trunc(min({e},ih)/2)*2)'",
+            e = edge
+        );
+        execute(
+            ffmpeg()
+                .arg("-i")
+                .arg(&input)
+                .args(["-vf", &scale])
+                .args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "23"])
+                .args(["-pix_fmt", "yuv420p", "-c:a", "aac", "-b:a", "128k"])
+                .args(["-movflags", "+faststart", "-map_metadata", "-1"])
+                .arg(&output),
+        )?;
+        renditions.push((size, std::fs::read(&output)?));
+    }
+    Ok(Transcoded {
+        poster: std::fs::read(&poster)?,
+        renditions,
+    })
+}
+
+fn ffmpeg() -> Command {
+    let mut command = Command::new(FFMPEG.as_str());
+    command
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    command
+}
+
+fn execute(command: &mut Command) -> AppResult<()> {
+    let status = command.status()?;
+    if !status.success() {
+        return Err(AppError::XCustomMessage("ffmpeg failed"));
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use std::{
+    env,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use sha2::Sha256;
+
+/// Origin of this API, used for signed URLs that must reach the `/media`
+/// route. Configured with `PUBLIC_BASE_URL`.
+static PUBLIC_BASE_URL: Lazy<String> = Lazy::new(|| {
+    env::var("PUBLIC_BASE_URL")
+        .unwrap_or_else(|_| "http://localhost:8080".to_string())
+        .trim_end_matches('/')
+        .to_string()
+});
+
+/// Origin for public media, e.g. a CDN in front of `/media`. Configured with
+/// `MEDIA_BASE_URL`; defaults to this API's `/media` route.
+static MEDIA_BASE_URL: Lazy<String> = Lazy::new(|| {
+    env::var("MEDIA_BASE_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| format!("{}/media", *PUBLIC_BASE_URL))
+});
+
+/// HMAC key for signed media URLs. Signing is disabled when
+/// `MEDIA_SIGNING_KEY` is not set.
+static SIGNING_KEY: Lazy<Option<Vec<u8>>> =
+    Lazy::new(|| env::var("MEDIA_SIGNING_KEY").ok().map(String::into_bytes));
+
+/// Lifetime of signed URLs embedded in API responses.
+pub const SIGNED_URL_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Public URL for a storage key. Values that are already absolute URLs
+/// (rows written before keys were stored) pass through unchanged.
+pub fn public_url(key: &str) -> String {
+    if key.starts_with("http://") || key.starts_with("https://") {
+        return key.to_string();
+    }
+    format!("{}/{}", *MEDIA_BASE_URL, key)
+}
+
+/// Expiring URL for a storage key, served by the `/media` route. Falls back
+/// to [`public_url`] when signing is disabled.
+pub fn signed_url(key: &str, expires_in: Duration) -> String {
+    let Some(secret) = SIGNING_KEY.as_ref() else {
+        return public_url(key);
+    };
+    let expires = now() + expires_in.as_secs();
+    format!(
+        "{}/media/{}?expires={}&signature={}",
+        *PUBLIC_BASE_URL,
+        key,
+        expires,
+        sign(secret, key, expires)
+    )
+}
+
+pub fn signing_enabled() -> bool {
+    SIGNING_KEY.is_some()
+}
+
+/// Checks a signature produced by [`signed_url`].
+pub fn verify(key: &str, expires: u64, signature: &str) -> bool {
+    let Some(secret) = SIGNING_KEY.as_ref() else {
+        return false;
+    };
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    expires >= now() && mac(secret, key, expires).verify_slice(&signature).is_ok()
+}
+
+fn sign(secret: &[u8], key: &str, expires: u64) -> String {
+    hex::encode(mac(secret, key, expires).finalize().into_bytes())
+}
+
+fn mac(secret: &[u8], key: &str, expires: u64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(key.as_bytes());
+    mac.update(b"\n");
+    mac.update(expires.to_string().as_bytes());
+    mac
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,71 @@
+use uuid::Uuid;
+use validator::ValidationError;
+
+use crate::{AppResult, error::AppError, media::store::MediaStore};
+
+/// Upper bound on the size of an uploaded video.
+pub const MAX_VIDEO_BYTES: usize = 100 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    Mp4,
+    WebM,
+    Mov,
+}
+
+impl VideoFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            VideoFormat::Mp4 => "mp4",
+            VideoFormat::WebM => "webm",
+            VideoFormat::Mov => "mov",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            VideoFormat::Mp4 => "video/mp4",
+            VideoFormat::WebM => "video/webm",
+            VideoFormat::Mov => "video/quicktime",
+        }
+    }
+}
+
+/// Recognizes MP4/MOV (ISO base media `ftyp` box) and WebM (EBML header
+/// with a `webm` doctype) from magic bytes.
+pub fn sniff(bytes: &[u8]) -> Option<VideoFormat> {
+    if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" {
+        return Some(if &bytes[8..12] == b"qt  " {
+            VideoFormat::Mov
+        } else {
+            VideoFormat::Mp4
+        });
+    }
+    if bytes.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        let header = &bytes[..bytes.len().min(64)];
+        if header.windows(4).any(|w| w == b"webm") {
+            return Some(VideoFormat::WebM);
+        }
+    }
+    None
+}
+
+/// Writes an uploaded video as-is under `<dir>/` and returns its storage
+/// key. Renditions are produced later by the transcode worker.
+pub async fn store_source(
+    store: &dyn MediaStore,
+    bytes: Vec<u8>,
+    format: VideoFormat,
+    dir: &str,
+) -> AppResult<String> {
+    if bytes.len() > MAX_VIDEO_BYTES {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Videos must be at most 100 MB",
+        )));
+    }
+    let storage_key = format!("{}/{}_source.{}", dir, Uuid::new_v4(), format.extension());
+    store
+        .put(&storage_key, bytes, format.content_type())
+        .await?;
+    Ok(storage_key)
+}
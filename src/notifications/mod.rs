@@ -0,0 +1,15 @@
+use rocket::{Route, routes};
+
+use crate::notifications::notification_service::*;
+
+pub mod model;
+pub mod notification_service;
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_notifications,
+        get_unread_count,
+        mark_read,
+        mark_all_read
+    ]
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use surrealdb_types::{Datetime, RecordId, SurrealValue, ToSql};
+
+use crate::{
+    db::make_cursor,
+    posts::model::{AuthorSummary, AuthorSummaryResponse},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, SurrealValue)]
+#[serde(rename_all = "lowercase")]
+#[surreal(untagged, lowercase)]
+pub enum NotificationKind {
+    Mention,
+    Repost,
+    Quote,
+}
+
+/// A notification as listed to its recipient. `post` is the post carrying
+/// the mention, or the repost or quote; `comment` is set when the mention is
+/// in a comment rather than the post caption.
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct Notification {
+    pub id: RecordId,
+    pub kind: NotificationKind,
+    pub actor: AuthorSummary,
+    pub post: Option<RecordId>,
+    pub comment: Option<RecordId>,
+    pub read: bool,
+    pub created_at: Datetime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationResponse {
+    pub id: String,
+    pub kind: NotificationKind,
+    pub actor: AuthorSummaryResponse,
+    pub post: Option<String>,
+    pub comment: Option<String>,
+    pub read: bool,
+    pub created_at: Datetime,
+    /// Pass as `cursor` to continue after this notification.
+    pub cursor: String,
+}
+
+impl From<Notification> for NotificationResponse {
+    fn from(notification: Notification) -> Self {
+        let id = notification.id.to_sql();
+        let cursor = make_cursor(&notification.created_at, &id);
+        Self {
+            id,
+            kind: notification.kind,
+            actor: notification.actor.into(),
+            post: notification.post.map(|p| p.to_sql()),
+            comment: notification.comment.map(|c| c.to_sql()),
+            read: notification.read,
+            created_at: notification.created_at,
+            cursor,
+        }
+    }
+}
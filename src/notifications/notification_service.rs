@@ -0,0 +1,184 @@
+use rocket::{State, get, put, serde::json::Json};
+use serde_json::{Value, json};
+use surrealdb_types::RecordId;
+
+use crate::{
+    AppResult, DB,
+    db::{parse_cursor, parse_thing},
+    error::AppError,
+    jwt::AuthUser,
+    notifications::model::{Notification, NotificationKind, NotificationResponse},
+    posts::{enrich_service, model::Post},
+    users::model::Mention,
+};
+
+/// Brings the mention notifications for a post caption (`comment` unset) or
+/// a comment in line with `mentions`: accounts that are no longer mentioned
+/// lose their notification and newly mentioned ones get one. Accounts that
+/// were already notified are not notified again, and `actor` never notifies
+/// themselves. Only accounts that may see `post` are notified.
+pub async fn sync_mentions(
+    db: &DB,
+    actor: &RecordId,
+    post: &Post,
+    comment: Option<&RecordId>,
+    mentions: &[Mention],
+) -> AppResult<()> {
+    let mut recipients: Vec<RecordId> = Vec::new();
+    for mention in mentions {
+        if mention.user != *actor && !recipients.contains(&mention.user) {
+            recipients.push(mention.user.clone());
+        }
+    }
+    let recipients = enrich_service::visible_to(db, post, recipients).await?;
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            DELETE notifications
+                WHERE kind = 'mention'
+                AND post = $post
+                AND comment = $comment
+                AND recipient NOT IN $recipients;
+            LET $notified = SELECT VALUE recipient FROM notifications
+                WHERE kind = 'mention'
+                AND post = $post
+                AND comment = $comment;
+            FOR $user IN array::complement($recipients, $notified) {
+                CREATE notifications SET
+                    recipient = $user,
+                    actor = $actor,
+                    kind = 'mention',
+                    post = $post,
+                    comment = $comment,
+                    read = false,
+                    created_at = time::now();
+            };
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("actor", actor.clone()))
+        .bind(("post", post.id.clone()))
+        .bind(("comment", comment.cloned()))
+        .bind(("recipients", recipients))
+        .await?;
+    res.check()?;
+    Ok(())
+}
+
+/// Notifies `recipient` that `actor` did something with `post`. Acting on
+/// your own content does not notify.
+pub async fn notify(
+    db: &DB,
+    recipient: &RecordId,
+    actor: &RecordId,
+    kind: NotificationKind,
+    post: &RecordId,
+) -> AppResult<()> {
+    if recipient == actor {
+        return Ok(());
+    }
+    let res = db
+        .query(
+            "
+            CREATE notifications SET
+                recipient = $recipient,
+                actor = $actor,
+                kind = $kind,
+                post = $post,
+                read = false,
+                created_at = time::now();
+            ",
+        )
+        .bind(("recipient", recipient.clone()))
+        .bind(("actor", actor.clone()))
+        .bind(("kind", kind))
+        .bind(("post", post.clone()))
+        .await?;
+    res.check()?;
+    Ok(())
+}
+
+/// The caller's notifications, newest first. Each carries a `cursor` to
+/// pass back for the next page.
+#[get("/notifications?<cursor>&<limit>")]
+pub async fn get_notifications(
+    cursor: Option<String>,
+    limit: Option<u32>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<NotificationResponse>>> {
+    let limit = limit.unwrap_or(20).min(100);
+    let (cursor_at, cursor_id) = cursor.as_deref().map(parse_cursor).transpose()?.unzip();
+    let notifications: Vec<Notification> = db
+        .query(
+            "
+            SELECT
+                id,
+                kind,
+                actor.{ id, username, profile_picture } AS actor,
+                post,
+                comment,
+                read,
+                created_at
+            FROM notifications
+            WHERE recipient = $uid
+            AND (
+                $cursor_at = NONE
+                OR created_at < $cursor_at
+                OR (created_at = $cursor_at AND id < $cursor_id)
+            )
+            ORDER BY created_at DESC, id DESC
+            LIMIT $limit
+            ",
+        )
+        .bind(("uid", parse_thing(&auth.user_id)?))
+        .bind(("cursor_at", cursor_at))
+        .bind(("cursor_id", cursor_id))
+        .bind(("limit", limit))
+        .await?
+        .take(0)?;
+    Ok(Json(notifications.into_iter().map(Into::into).collect()))
+}
+
+#[get("/unread-count")]
+pub async fn get_unread_count(db: &State<DB>, auth: AuthUser) -> AppResult<Value> {
+    let count: Option<usize> = db
+        .query(
+            "
+            SELECT VALUE count() FROM notifications
+                WHERE recipient = $uid AND read = false
+                GROUP ALL
+            ",
+        )
+        .bind(("uid", parse_thing(&auth.user_id)?))
+        .await?
+        .take(0)?;
+    Ok(json!({
+        "unread_count": count.unwrap_or(0)
+    }))
+}
+
+#[put("/read/<id>")]
+pub async fn mark_read(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let updated: Vec<RecordId> = db
+        .query("UPDATE notifications SET read = true WHERE id = $id AND recipient = $uid RETURN VALUE id")
+        .bind(("id", parse_thing(id)?))
+        .bind(("uid", parse_thing(&auth.user_id)?))
+        .await?
+        .take(0)?;
+    if updated.is_empty() {
+        return Err(AppError::XCustomMessage("Notification not found"));
+    }
+    Ok("Notification read".to_string())
+}
+
+#[put("/read-all")]
+pub async fn mark_all_read(db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let res = db
+        .query("UPDATE notifications SET read = true WHERE recipient = $uid AND read = false")
+        .bind(("uid", parse_thing(&auth.user_id)?))
+        .await?;
+    res.check()?;
+    Ok("All notifications read".to_string())
+}
@@ -0,0 +1,335 @@
+use std::collections::{HashMap, HashSet};
+
+use surrealdb::IndexedResults;
+use surrealdb_types::{RecordId, ToSql};
+
+use crate::{
+    AppResult, DB,
+    error::AppError,
+    posts::model::{
+        AuthorSummary, MediaResponse, PollVote, Post, PostKind, PostResponse, PostStatus,
+        PostVisibility, Reaction, ReactionTally, ViewerReaction,
+    },
+};
+
+/// How a viewer relates to a set of authors, keyed by `to_sql()` ids.
+struct Audience {
+    viewer: String,
+    private: HashSet<String>,
+    following: HashSet<String>,
+    /// Authors who have the viewer on their close friends list.
+    close_to: HashSet<String>,
+    /// Authors blocked by or blocking the viewer.
+    blocked: HashSet<String>,
+}
+
+impl Audience {
+    /// Posts that are archived, or still processing or failed, are only
+    /// shown to their author.
+    fn can_see(
+        &self,
+        author: &str,
+        visibility: PostVisibility,
+        archived: bool,
+        status: PostStatus,
+    ) -> bool {
+        if author == self.viewer {
+            return true;
+        }
+        if archived || status != PostStatus::Ready || self.blocked.contains(author) {
+            return false;
+        }
+        match visibility {
+            PostVisibility::Public => {
+                !self.private.contains(author) || self.following.contains(author)
+            }
+            PostVisibility::Followers => self.following.contains(author),
+            PostVisibility::Friends => self.close_to.contains(author),
+            PostVisibility::Me => false,
+        }
+    }
+}
+
+/// The audience queries shared by [`enrich_posts`] and [`ensure_visible`],
+/// for `$viewer` and `$authors`.
+const AUDIENCE_SQL: &str = "
+    SELECT VALUE id FROM $authors WHERE is_private = true;
+    SELECT VALUE following_id FROM follows
+        WHERE follower_id = $viewer
+        AND following_id IN $authors;
+    SELECT VALUE in FROM close_friends WHERE out = $viewer AND in IN $authors;
+    SELECT VALUE out FROM blocks WHERE in = $viewer AND out IN $authors;
+    SELECT VALUE in FROM blocks WHERE out = $viewer AND in IN $authors;
+";
+
+/// Reads an [`Audience`] from the five result sets of [`AUDIENCE_SQL`]
+/// starting at `first`.
+fn take_audience(res: &mut IndexedResults, viewer: &RecordId, first: usize) -> AppResult<Audience> {
+    let mut blocked = take_ids(res, first + 3)?;
+    blocked.extend(take_ids(res, first + 4)?);
+    Ok(Audience {
+        viewer: viewer.to_sql(),
+        private: take_ids(res, first)?,
+        following: take_ids(res, first + 1)?,
+        close_to: take_ids(res, first + 2)?,
+        blocked,
+    })
+}
+
+fn take_ids(res: &mut IndexedResults, index: usize) -> AppResult<HashSet<String>> {
+    Ok(res
+        .take::<Vec<RecordId>>(index)?
+        .iter()
+        .map(|id| id.to_sql())
+        .collect())
+}
+
+/// Fails with "Post not found" unless `viewer` may see `post`, for endpoints
+/// that act on a post without returning it.
+pub async fn ensure_visible(db: &DB, viewer: &RecordId, post: &Post) -> AppResult<()> {
+    let mut res = db
+        .query(AUDIENCE_SQL)
+        .bind(("viewer", viewer.clone()))
+        .bind(("authors", vec![post.uid.clone()]))
+        .await?;
+    let audience = take_audience(&mut res, viewer, 0)?;
+    if !audience.can_see(
+        &post.uid.to_sql(),
+        post.visibility,
+        post.archived,
+        post.status,
+    ) {
+        return Err(AppError::XCustomMessage("Post not found"));
+    }
+    Ok(())
+}
+
+/// The accounts among `users` that may see `post`, in their original
+/// order, for fanning out notifications about it.
+pub async fn visible_to(db: &DB, post: &Post, users: Vec<RecordId>) -> AppResult<Vec<RecordId>> {
+    if users.is_empty() {
+        return Ok(users);
+    }
+    let mut res = db
+        .query(
+            "
+            SELECT VALUE is_private FROM ONLY $author;
+            SELECT VALUE follower_id FROM follows
+                WHERE following_id = $author
+                AND follower_id IN $users;
+            SELECT VALUE out FROM close_friends WHERE in = $author AND out IN $users;
+            SELECT VALUE out FROM blocks WHERE in = $author AND out IN $users;
+            SELECT VALUE in FROM blocks WHERE out = $author AND in IN $users;
+            ",
+        )
+        .bind(("author", post.uid.clone()))
+        .bind(("users", users.clone()))
+        .await?;
+    let author = post.uid.to_sql();
+    let private = res.take::<Option<bool>>(0)?.unwrap_or(false);
+    let followers = take_ids(&mut res, 1)?;
+    let close_friends = take_ids(&mut res, 2)?;
+    let mut blocked = take_ids(&mut res, 3)?;
+    blocked.extend(take_ids(&mut res, 4)?);
+    let only_author = |included: bool| {
+        if included {
+            HashSet::from([author.clone()])
+        } else {
+            HashSet::new()
+        }
+    };
+    Ok(users
+        .into_iter()
+        .filter(|user| {
+            let user_id = user.to_sql();
+            let audience = Audience {
+                private: only_author(private),
+                following: only_author(followers.contains(&user_id)),
+                close_to: only_author(close_friends.contains(&user_id)),
+                blocked: only_author(blocked.contains(&user_id)),
+                viewer: user_id,
+            };
+            audience.can_see(&author, post.visibility, post.archived, post.status)
+        })
+        .collect())
+}
+
+/// Turns a page of posts into responses for `viewer`, resolving per-viewer
+/// reactions, bookmarks, poll votes and author summaries for the whole page in a single
+/// round trip. Media of private authors gets signed, expiring URLs. Poll
+/// tallies the author chose to hide stay hidden until the viewer has voted
+/// or the poll has ended.
+///
+/// Reposts and quotes get their original embedded when the viewer may see
+/// it. Reposts of an original the viewer may not see (deleted, from a
+/// private account they do not follow, or blocked) are left out.
+pub async fn enrich_posts(
+    db: &DB,
+    viewer: &RecordId,
+    mut posts: Vec<Post>,
+) -> AppResult<Vec<PostResponse>> {
+    if posts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let page_len = posts.len();
+    let mut original_ids: Vec<RecordId> = Vec::new();
+    for post in &posts {
+        if let Some(original) = &post.original
+            && !original_ids.contains(original)
+        {
+            original_ids.push(original.clone());
+        }
+    }
+    let mut original_count = 0;
+    if !original_ids.is_empty() {
+        let originals: Vec<Post> = db
+            .query("SELECT * FROM $originals")
+            .bind(("originals", original_ids))
+            .await?
+            .take(0)?;
+        original_count = originals.len();
+        posts.extend(originals);
+    }
+
+    let post_ids: Vec<RecordId> = posts.iter().map(|p| p.id.clone()).collect();
+    let mut author_ids: Vec<RecordId> = Vec::new();
+    for post in &posts {
+        if !author_ids.contains(&post.uid) {
+            author_ids.push(post.uid.clone());
+        }
+    }
+
+    let mut res = db
+        .query(format!(
+            "
+            SELECT out AS post, reaction FROM reactions
+                WHERE in = $viewer
+                AND out IN $posts;
+            SELECT out AS post, reaction, count() AS count FROM reactions
+                WHERE out IN $posts
+                GROUP BY post, reaction;
+            SELECT id, username, profile_picture, is_private FROM $authors;
+            SELECT VALUE out FROM bookmarks
+                WHERE in = $viewer
+                AND out IN $posts;
+            SELECT out AS post, choices FROM poll_votes
+                WHERE in = $viewer
+                AND out IN $posts;
+            {}
+            ",
+            AUDIENCE_SQL
+        ))
+        .bind(("posts", post_ids))
+        .bind(("viewer", viewer.clone()))
+        .bind(("authors", author_ids))
+        .await?;
+    let mine: HashMap<String, Reaction> = res
+        .take::<Vec<ViewerReaction>>(0)?
+        .into_iter()
+        .map(|r| (r.post.to_sql(), r.reaction))
+        .collect();
+    let mut tallies: HashMap<String, HashMap<Reaction, usize>> = HashMap::new();
+    for tally in res.take::<Vec<ReactionTally>>(1)? {
+        tallies
+            .entry(tally.post.to_sql())
+            .or_default()
+            .insert(tally.reaction, tally.count);
+    }
+    let authors: HashMap<String, AuthorSummary> = res
+        .take::<Vec<AuthorSummary>>(2)?
+        .into_iter()
+        .map(|a| (a.id.to_sql(), a))
+        .collect();
+    let saved: HashSet<String> = res
+        .take::<Vec<RecordId>>(3)?
+        .iter()
+        .map(|id| id.to_sql())
+        .collect();
+    let mut votes: HashMap<String, Vec<usize>> = res
+        .take::<Vec<PollVote>>(4)?
+        .into_iter()
+        .map(|v| (v.post.to_sql(), v.choices))
+        .collect();
+    let audience = take_audience(&mut res, viewer, 5)?;
+
+    let mut responses: Vec<PostResponse> = posts
+        .into_iter()
+        .enumerate()
+        .filter(|(index, post)| {
+            *index >= page_len
+                || audience.can_see(
+                    &post.uid.to_sql(),
+                    post.visibility,
+                    post.archived,
+                    post.status,
+                )
+        })
+        .map(|(_, post)| {
+            let id = post.id.to_sql();
+            let author = authors.get(&post.uid.to_sql());
+            let private = author.is_some_and(|a| a.is_private);
+            let author = author.map(|a| a.clone().into());
+            let restricted = private || post.visibility != PostVisibility::Public || post.archived;
+            let mut response: PostResponse = post.into();
+            if restricted {
+                response.media.iter_mut().for_each(MediaResponse::sign);
+            }
+            response.my_reaction = mine.get(&id).copied();
+            response.liked_by_user = response.my_reaction.is_some();
+            response.saved_by_user = saved.contains(&id);
+            response.reactions = tallies.remove(&id).unwrap_or_default();
+            response.author = author;
+            if response.uid != audience.viewer {
+                response.views_count = None;
+            }
+            if let Some(poll) = response.poll.as_mut() {
+                poll.my_choices = votes.remove(&id).unwrap_or_default();
+                if poll.hide_results
+                    && poll.my_choices.is_empty()
+                    && !poll.closed
+                    && response.uid != audience.viewer
+                {
+                    poll.counts = None;
+                    poll.voters_count = None;
+                }
+            }
+            response
+        })
+        .collect();
+    let originals: HashMap<String, PostResponse> = responses
+        .split_off(responses.len() - original_count)
+        .into_iter()
+        .filter(|original| {
+            original.status == PostStatus::Ready
+                && audience.can_see(
+                    &original.uid,
+                    original.visibility,
+                    original.archived,
+                    original.status,
+                )
+        })
+        .map(|original| (original.id.clone(), original))
+        .collect();
+
+    Ok(responses
+        .into_iter()
+        .filter_map(|mut response| {
+            let original = response
+                .original_id
+                .as_ref()
+                .and_then(|id| originals.get(id));
+            if response.kind == PostKind::Repost && original.is_none() {
+                return None;
+            }
+            response.original = original.map(|o| Box::new(o.clone()));
+            Some(response)
+        })
+        .collect())
+}
+
+pub async fn enrich_post(db: &DB, viewer: &RecordId, post: Post) -> AppResult<PostResponse> {
+    enrich_posts(db, viewer, vec![post])
+        .await?
+        .pop()
+        .ok_or(AppError::XCustomMessage("Post not found"))
+}
@@ -1,9 +1,15 @@
 use rocket::{Route, routes};
 
+use crate::posts::poll_service::*;
 use crate::posts::post_service::*;
+use crate::posts::repost_service::*;
 
+pub mod enrich_service;
 pub mod model;
+pub mod poll_service;
 pub mod post_service;
+pub mod repost_service;
+pub mod timeline_service;
 
 pub fn routes() -> Vec<Route> {
     routes![
@@ -12,6 +18,18 @@ pub fn routes() -> Vec<Route> {
         get_feed,
         get_post_by_id,
         like_post,
-        get_likes
+        vote,
+        get_reactions,
+        edit_post,
+        update_visibility,
+        pin_post,
+        unpin_post,
+        archive_post,
+        unarchive_post,
+        get_post_edits,
+        delete_post,
+        repost,
+        undo_repost,
+        quote_post
     ]
 }
@@ -1,51 +1,509 @@
-use rocket::{FromForm, fs::TempFile};
+use std::collections::HashMap;
+
+use chrono::Utc;
+use rocket::{FromForm, FromFormField, fs::TempFile};
 use serde::{Deserialize, Serialize};
 use surrealdb_types::{Datetime, RecordId, SurrealValue, ToSql};
+use validator::Validate;
+
+use crate::{
+    db::make_cursor,
+    media::{
+        model::{Placeholder, Rendition, RenditionResponse, RenditionSize},
+        urls,
+    },
+    users::model::{Mention, MentionResponse},
+};
 
 #[derive(Debug, Serialize, Deserialize, SurrealValue)]
 pub struct Post {
-    id: RecordId,
-    content: String,
-    caption: String,
-    uid: RecordId,
-    likes_count: usize,
-    created_at: Datetime,
+    pub id: RecordId,
+    #[surreal(default)]
+    pub kind: PostKind,
+    #[surreal(default)]
+    pub media: Vec<Media>,
+    pub caption: String,
+    #[surreal(default)]
+    pub mentions: Vec<Mention>,
+    pub link_url: Option<String>,
+    #[surreal(default)]
+    pub status: PostStatus,
+    #[surreal(default)]
+    pub visibility: PostVisibility,
+    pub uid: RecordId,
+    /// The shared post, for reposts and quotes.
+    #[surreal(default)]
+    pub original: Option<RecordId>,
+    #[surreal(default)]
+    pub poll: Option<Poll>,
+    /// Set while the post is pinned to the top of its author's profile.
+    #[surreal(default)]
+    pub pinned_at: Option<Datetime>,
+    /// Archived posts are hidden from everyone but their author.
+    #[surreal(default)]
+    pub archived: bool,
+    pub likes_count: usize,
+    #[surreal(default)]
+    pub comments_count: usize,
+    #[surreal(default)]
+    pub reposts_count: usize,
+    /// Impressions, deduplicated per viewer and window.
+    #[surreal(default)]
+    pub views_count: usize,
+    pub created_at: Datetime,
+    pub edited_at: Option<Datetime>,
+}
+
+/// Upper bound on the number of media items in a carousel post.
+pub const MAX_MEDIA_ITEMS: usize = 10;
+/// Caption limit for media and link posts.
+pub const MAX_CAPTION_LEN: usize = 2200;
+/// Body limit for text-only posts.
+pub const MAX_TEXT_POST_LEN: usize = 5000;
+/// Bounds on the number of poll options.
+pub const MIN_POLL_OPTIONS: usize = 2;
+pub const MAX_POLL_OPTIONS: usize = 4;
+/// Length limit for a single poll option.
+pub const MAX_POLL_OPTION_LEN: usize = 80;
+/// How far ahead a poll may end.
+pub const MAX_POLL_DAYS: i64 = 7;
+/// Upper bound on the number of posts pinned to a profile.
+pub const MAX_PINNED_POSTS: usize = 3;
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, SurrealValue, FromFormField,
+)]
+#[serde(rename_all = "lowercase")]
+#[surreal(untagged, lowercase)]
+pub enum PostKind {
+    #[default]
+    Image,
+    Text,
+    Link,
+    /// A plain boost of `original`, without caption or media.
+    Repost,
+    /// A text post commenting on `original`.
+    Quote,
+}
+
+/// Posts with video stay `processing` until every video has been
+/// transcoded; they are only fanned out to followers once `ready`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, SurrealValue)]
+#[serde(rename_all = "lowercase")]
+#[surreal(untagged, lowercase)]
+pub enum PostStatus {
+    #[default]
+    Ready,
+    Processing,
+    Failed,
+}
+
+/// Who may see a post besides its author.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, SurrealValue, FromFormField,
+)]
+#[serde(rename_all = "lowercase")]
+#[surreal(untagged, lowercase)]
+pub enum PostVisibility {
+    /// Everyone, or only followers when the account is private.
+    #[default]
+    Public,
+    Followers,
+    /// The author's close friends list.
+    Friends,
+    /// Only the author.
+    Me,
+}
+
+/// A poll attached to a post. Tallies are kept on the post and updated in
+/// the same transaction as the vote.
+#[derive(Debug, Clone, Serialize, Deserialize, SurrealValue)]
+pub struct Poll {
+    pub options: Vec<String>,
+    /// Votes per option, in option order.
+    pub counts: Vec<usize>,
+    pub voters_count: usize,
+    /// Whether a voter may pick more than one option.
+    pub multiple: bool,
+    /// Hides the tallies from everyone but the author until they have voted
+    /// or the poll has ended.
+    pub hide_results: bool,
+    pub ends_at: Datetime,
+}
+
+impl Poll {
+    pub fn is_closed(&self) -> bool {
+        *self.ends_at <= Utc::now()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollResponse {
+    pub options: Vec<String>,
+    /// `None` while the results are hidden from the viewer.
+    pub counts: Option<Vec<usize>>,
+    pub voters_count: Option<usize>,
+    pub multiple: bool,
+    pub hide_results: bool,
+    pub ends_at: Datetime,
+    pub closed: bool,
+    /// Indexes of the options the viewer voted for; empty if they have not
+    /// voted.
+    pub my_choices: Vec<usize>,
+}
+
+impl From<Poll> for PollResponse {
+    fn from(poll: Poll) -> Self {
+        Self {
+            closed: poll.is_closed(),
+            options: poll.options,
+            counts: Some(poll.counts),
+            voters_count: Some(poll.voters_count),
+            multiple: poll.multiple,
+            hide_results: poll.hide_results,
+            ends_at: poll.ends_at,
+            my_choices: Vec::new(),
+        }
+    }
 }
 
+/// A viewer's vote, as read back when enriching posts.
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct PollVote {
+    pub post: RecordId,
+    pub choices: Vec<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VoteRequest {
+    /// Indexes into the poll's options.
+    pub choices: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, SurrealValue)]
+#[serde(rename_all = "lowercase")]
+#[surreal(untagged, lowercase)]
+pub enum MediaKind {
+    Image,
+    Video,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SurrealValue)]
+pub struct Media {
+    pub position: usize,
+    pub kind: MediaKind,
+    pub storage_key: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub alt_text: Option<String>,
+    #[surreal(default)]
+    pub renditions: Vec<Rendition>,
+    /// Poster frame renditions; only set for transcoded videos.
+    #[surreal(default)]
+    pub poster: Vec<Rendition>,
+    /// For videos, computed from the poster frame.
+    #[surreal(default)]
+    pub placeholder: Option<Placeholder>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaResponse {
+    pub position: usize,
+    pub kind: MediaKind,
+    pub storage_key: String,
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub alt_text: Option<String>,
+    pub renditions: Vec<RenditionResponse>,
+    pub poster: Vec<RenditionResponse>,
+    pub placeholder: Option<Placeholder>,
+}
+
+impl From<Media> for MediaResponse {
+    fn from(media: Media) -> Self {
+        Self {
+            url: urls::public_url(&media.storage_key),
+            position: media.position,
+            kind: media.kind,
+            storage_key: media.storage_key,
+            width: media.width,
+            height: media.height,
+            alt_text: media.alt_text,
+            renditions: media.renditions.into_iter().map(Into::into).collect(),
+            poster: media.poster.into_iter().map(Into::into).collect(),
+            placeholder: media.placeholder,
+        }
+    }
+}
+
+impl MediaResponse {
+    /// Swaps every URL for an expiring signed one, for media that must not
+    /// be world-readable.
+    pub fn sign(&mut self) {
+        self.url = urls::signed_url(&self.storage_key, urls::SIGNED_URL_TTL);
+        self.renditions.iter_mut().for_each(RenditionResponse::sign);
+        self.poster.iter_mut().for_each(RenditionResponse::sign);
+    }
+}
+
+/// Multipart body for `/post`. `content` and `alt_text` are repeated fields
+/// matched up by position. When `kind` is omitted it is inferred from the
+/// payload: a `link_url` makes a link post, attached files an image post and
+/// anything else a text post.
 #[derive(Debug, FromForm)]
 pub struct PostFormRequest<'r> {
-    pub content: TempFile<'r>,
+    pub kind: Option<PostKind>,
+    pub content: Vec<TempFile<'r>>,
+    pub alt_text: Vec<String>,
+    #[field(default = String::new())]
+    pub caption: String,
+    pub link_url: Option<String>,
+    pub visibility: Option<PostVisibility>,
+    /// Repeated; attaching two or more options adds a poll.
+    pub poll_option: Vec<String>,
+    /// RFC 3339 end time of the poll.
+    pub poll_ends_at: Option<String>,
+    #[field(default = false)]
+    pub poll_multiple: bool,
+    #[field(default = false)]
+    pub poll_hide_results: bool,
+}
+
+/// What an author composed, before it becomes a post.
+#[derive(Debug)]
+pub struct NewPost {
+    pub kind: PostKind,
+    pub media: Vec<Media>,
     pub caption: String,
+    pub link_url: Option<String>,
+    pub visibility: PostVisibility,
+    pub poll: Option<Poll>,
+    /// The draft this post is published from. It is deleted in the
+    /// transaction that creates the post, which takes over its media.
+    pub draft: Option<RecordId>,
 }
 
 #[derive(Debug, Serialize, SurrealValue)]
 pub struct PostRequest {
-    pub content: String,
+    pub kind: PostKind,
+    pub media: Vec<Media>,
     pub caption: String,
+    pub mentions: Vec<Mention>,
+    pub link_url: Option<String>,
+    pub status: PostStatus,
+    pub visibility: PostVisibility,
     pub uid: RecordId,
+    pub original: Option<RecordId>,
+    pub poll: Option<Poll>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PostResponse {
     pub id: String,
-    pub content: String,
+    pub kind: PostKind,
+    pub media: Vec<MediaResponse>,
     pub caption: String,
+    pub mentions: Vec<MentionResponse>,
+    pub link_url: Option<String>,
+    pub status: PostStatus,
+    pub visibility: PostVisibility,
     pub uid: String,
+    /// Set for reposts and quotes. `original` is left empty when the shared
+    /// post was deleted or is hidden from the viewer.
+    pub original_id: Option<String>,
+    pub original: Option<Box<PostResponse>>,
+    pub poll: Option<PollResponse>,
+    pub pinned_at: Option<Datetime>,
+    pub archived: bool,
     pub likes_count: usize,
+    pub comments_count: usize,
+    pub reposts_count: usize,
+    /// Only reported to the post's author.
+    pub views_count: Option<usize>,
     pub created_at: Datetime,
+    pub edited_at: Option<Datetime>,
     pub liked_by_user: bool,
+    pub saved_by_user: bool,
+    pub my_reaction: Option<Reaction>,
+    pub reactions: HashMap<Reaction, usize>,
+    pub author: Option<AuthorSummaryResponse>,
+    /// Set on lists paged by cursor; pass it back to continue after this
+    /// post.
+    pub cursor: Option<String>,
 }
 
 impl From<Post> for PostResponse {
     fn from(post: Post) -> Self {
         Self {
             id: post.id.to_sql(),
-            content: post.content,
+            kind: post.kind,
+            media: post.media.into_iter().map(Into::into).collect(),
             caption: post.caption,
+            mentions: post.mentions.into_iter().map(Into::into).collect(),
+            link_url: post.link_url,
+            status: post.status,
+            visibility: post.visibility,
             uid: post.uid.to_sql(),
+            original_id: post.original.map(|o| o.to_sql()),
+            original: None,
+            poll: post.poll.map(Into::into),
+            pinned_at: post.pinned_at,
+            archived: post.archived,
             likes_count: post.likes_count,
+            comments_count: post.comments_count,
+            reposts_count: post.reposts_count,
+            views_count: Some(post.views_count),
             created_at: post.created_at,
+            edited_at: post.edited_at,
             liked_by_user: false,
+            saved_by_user: false,
+            my_reaction: None,
+            reactions: HashMap::new(),
+            author: None,
+            cursor: None,
+        }
+    }
+}
+
+/// Length of `text_preview` in profile grid items.
+const GRID_PREVIEW_LEN: usize = 120;
+
+/// A post as a profile grid cell: one thumbnail and the counts, without
+/// the full media list or per-viewer state.
+#[derive(Debug, Serialize)]
+pub struct PostGridItemResponse {
+    pub id: String,
+    pub kind: PostKind,
+    pub status: PostStatus,
+    /// Smallest rendition of the first item; the poster frame for videos.
+    pub thumbnail: Option<RenditionResponse>,
+    pub placeholder: Option<Placeholder>,
+    pub media_count: usize,
+    pub has_video: bool,
+    pub has_poll: bool,
+    /// Start of the caption, for posts without media.
+    pub text_preview: Option<String>,
+    pub likes_count: usize,
+    pub comments_count: usize,
+    pub reposts_count: usize,
+    /// Only reported to the post's author.
+    pub views_count: Option<usize>,
+    pub pinned: bool,
+    pub created_at: Datetime,
+    /// Pass as `cursor` to continue after this post. Not set on pinned
+    /// posts, which only come with the first page.
+    pub cursor: Option<String>,
+}
+
+impl From<Post> for PostGridItemResponse {
+    fn from(post: Post) -> Self {
+        let first = post.media.first();
+        let thumbnail = first.and_then(|media| {
+            let renditions = match media.kind {
+                MediaKind::Image => &media.renditions,
+                MediaKind::Video => &media.poster,
+            };
+            renditions
+                .iter()
+                .find(|r| r.size == RenditionSize::Thumbnail)
+                .or(renditions.first())
+                .cloned()
+                .map(Into::into)
+        });
+        let text_preview = if post.media.is_empty() && !post.caption.is_empty() {
+            Some(post.caption.chars().take(GRID_PREVIEW_LEN).collect())
+        } else {
+            None
+        };
+        let id = post.id.to_sql();
+        let cursor = post
+            .pinned_at
+            .is_none()
+            .then(|| make_cursor(&post.created_at, &id));
+        Self {
+            id,
+            kind: post.kind,
+            status: post.status,
+            thumbnail,
+            placeholder: first.and_then(|media| media.placeholder.clone()),
+            media_count: post.media.len(),
+            has_video: post.media.iter().any(|m| m.kind == MediaKind::Video),
+            has_poll: post.poll.is_some(),
+            text_preview,
+            likes_count: post.likes_count,
+            comments_count: post.comments_count,
+            reposts_count: post.reposts_count,
+            views_count: Some(post.views_count),
+            pinned: post.pinned_at.is_some(),
+            created_at: post.created_at,
+            cursor,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, SurrealValue)]
+pub struct AuthorSummary {
+    pub id: RecordId,
+    pub username: String,
+    pub profile_picture: Option<String>,
+    #[surreal(default)]
+    pub is_private: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorSummaryResponse {
+    pub id: String,
+    pub username: String,
+    pub profile_picture: Option<String>,
+}
+
+impl From<AuthorSummary> for AuthorSummaryResponse {
+    fn from(author: AuthorSummary) -> Self {
+        Self {
+            id: author.id.to_sql(),
+            username: author.username,
+            profile_picture: author.profile_picture.as_deref().map(urls::public_url),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct EditPostRequest {
+    #[validate(length(max = 5000, message = "Caption must be at most 5000 characters"))]
+    pub caption: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct QuoteRequest {
+    #[validate(length(min = 1, max = 2200, message = "Caption must be 1-2200 characters"))]
+    pub caption: String,
+    pub visibility: Option<PostVisibility>,
+}
+
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct PostEdit {
+    pub id: RecordId,
+    pub post: RecordId,
+    pub caption: String,
+    pub edited_at: Datetime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostEditResponse {
+    pub id: String,
+    pub post: String,
+    pub caption: String,
+    pub edited_at: Datetime,
+}
+
+impl From<PostEdit> for PostEditResponse {
+    fn from(edit: PostEdit) -> Self {
+        Self {
+            id: edit.id.to_sql(),
+            post: edit.post.to_sql(),
+            caption: edit.caption,
+            edited_at: edit.edited_at,
         }
     }
 }
@@ -56,26 +514,56 @@ pub struct FeedQuery {
     pub limit: Option<u32>,
 }
 
-#[derive(Debug, Deserialize, Serialize, SurrealValue)]
-pub struct Like {
-    pub id: RecordId,
-    pub post_id: RecordId,
-    pub user_ids: Vec<RecordId>,
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, SurrealValue, FromFormField,
+)]
+#[serde(rename_all = "lowercase")]
+#[surreal(untagged, lowercase)]
+pub enum Reaction {
+    Like,
+    Love,
+    Laugh,
+    Wow,
+    Sad,
+    Angry,
 }
 
-#[derive(Debug, Serialize, SurrealValue)]
-pub struct LikeResponse {
-    pub id: String,
-    pub post_id: String,
-    pub user_ids: Vec<String>,
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct ViewerReaction {
+    pub post: RecordId,
+    pub reaction: Reaction,
+}
+
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct ReactionTally {
+    pub post: RecordId,
+    pub reaction: Reaction,
+    pub count: usize,
+}
+
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct Reactor {
+    pub user: AuthorSummary,
+    pub reaction: Reaction,
+    pub created_at: Datetime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReactorResponse {
+    pub user: AuthorSummaryResponse,
+    pub reaction: Reaction,
+    pub created_at: Datetime,
+    /// Pass as `cursor` to continue after this reaction.
+    pub cursor: String,
 }
 
-impl From<Like> for LikeResponse {
-    fn from(like: Like) -> Self {
+impl From<Reactor> for ReactorResponse {
+    fn from(reactor: Reactor) -> Self {
         Self {
-            id: like.id.to_sql(),
-            post_id: like.post_id.to_sql(),
-            user_ids: like.user_ids.into_iter().map(|e| e.to_sql()).collect(),
+            cursor: make_cursor(&reactor.created_at, &reactor.user.id.to_sql()),
+            user: reactor.user.into(),
+            reaction: reactor.reaction,
+            created_at: reactor.created_at,
         }
     }
 }
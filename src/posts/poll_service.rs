@@ -0,0 +1,159 @@
+use std::str::FromStr;
+
+use chrono::{Duration, Utc};
+use rocket::{State, put, serde::json::Json};
+use surrealdb_types::Datetime;
+use validator::ValidationError;
+
+use crate::{
+    AppResult, DB,
+    db::parse_thing,
+    error::AppError,
+    jwt::AuthUser,
+    posts::{
+        enrich_service,
+        model::{
+            MAX_POLL_DAYS, MAX_POLL_OPTION_LEN, MAX_POLL_OPTIONS, MIN_POLL_OPTIONS, Poll,
+            PostFormRequest, PostResponse, PostStatus, VoteRequest,
+        },
+        post_service::find_post,
+    },
+};
+
+/// Votes in a post's poll. Each account votes once; the vote and the
+/// tallies are written in one transaction. Returns the post with the
+/// updated tallies.
+#[put("/vote/<id>", data = "<req>")]
+pub async fn vote(
+    id: &str,
+    req: Json<VoteRequest>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<PostResponse>> {
+    let uid = parse_thing(&auth.user_id)?;
+    let post = find_post(db, parse_thing(id)?).await?;
+    enrich_service::ensure_visible(db, &uid, &post).await?;
+    let poll = post
+        .poll
+        .as_ref()
+        .ok_or(AppError::XCustomMessage("Post has no poll"))?;
+    if post.status != PostStatus::Ready {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Post is still processing",
+        )));
+    }
+    if poll.is_closed() {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Poll has ended",
+        )));
+    }
+    let mut choices = req.choices.clone();
+    choices.sort_unstable();
+    choices.dedup();
+    if choices.is_empty() || choices.iter().any(|&choice| choice >= poll.options.len()) {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Invalid poll choice",
+        )));
+    }
+    if !poll.multiple && choices.len() > 1 {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "This poll allows a single choice",
+        )));
+    }
+    let mut res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+
+            LET $voted = (SELECT id FROM poll_votes WHERE in = $uid AND out = $pid) != [];
+            IF !$voted {
+                RELATE $uid->poll_votes->$pid SET
+                    choices = $choices,
+                    created_at = time::now();
+                UPDATE $pid SET
+                    poll.voters_count += 1,
+                    poll.counts = array::map(poll.counts, |$count, $index|
+                        IF $index IN $choices THEN $count + 1 ELSE $count END
+                    );
+            };
+            RETURN $voted;
+            COMMIT TRANSACTION;
+        ",
+        )
+        .bind(("uid", uid.clone()))
+        .bind(("pid", post.id.clone()))
+        .bind(("choices", choices))
+        .await?;
+    if res.take::<Option<bool>>(2)?.unwrap_or(false) {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Already voted in this poll",
+        )));
+    }
+    let post = find_post(db, post.id).await?;
+    let post = enrich_service::enrich_post(db, &uid, post).await?;
+    Ok(Json(post))
+}
+
+/// Builds the poll described by a post form, if any. A poll needs 2-4
+/// distinct options and an end time at most a week away.
+pub fn resolve_poll(form: &PostFormRequest<'_>) -> AppResult<Option<Poll>> {
+    if form.poll_option.is_empty() && form.poll_ends_at.is_none() {
+        return Ok(None);
+    }
+    let options: Vec<String> = form
+        .poll_option
+        .iter()
+        .map(|option| option.trim().to_string())
+        .collect();
+    if !(MIN_POLL_OPTIONS..=MAX_POLL_OPTIONS).contains(&options.len()) {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "A poll needs 2-4 options",
+        )));
+    }
+    if options
+        .iter()
+        .any(|option| option.is_empty() || option.chars().count() > MAX_POLL_OPTION_LEN)
+    {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Poll options must be 1-80 characters",
+        )));
+    }
+    for (index, option) in options.iter().enumerate() {
+        if options[..index]
+            .iter()
+            .any(|other| other.to_lowercase() == option.to_lowercase())
+        {
+            return Err(AppError::ValidationError(ValidationError::new(
+                "Poll options must be distinct",
+            )));
+        }
+    }
+    let ends_at = form
+        .poll_ends_at
+        .as_deref()
+        .ok_or(AppError::ValidationError(ValidationError::new(
+            "A poll needs an end time",
+        )))?;
+    let ends_at = Datetime::from_str(ends_at).map_err(|_| {
+        AppError::ValidationError(ValidationError::new("Invalid poll_ends_at format"))
+    })?;
+    let now = Utc::now();
+    if *ends_at <= now {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Poll must end in the future",
+        )));
+    }
+    if *ends_at > now + Duration::days(MAX_POLL_DAYS) {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "A poll can run for at most 7 days",
+        )));
+    }
+    Ok(Some(Poll {
+        counts: vec![0; options.len()],
+        options,
+        voters_count: 0,
+        multiple: form.poll_multiple,
+        hide_results: form.poll_hide_results,
+        ends_at,
+    }))
+}
@@ -1,104 +1,438 @@
-use rocket::{State, form::Form, get, post, put, serde::json::Json};
+use rocket::{
+    State, delete,
+    form::{self, Form},
+    fs::TempFile,
+    get, post, put,
+    serde::json::Json,
+};
 
-use uuid::Uuid;
-use validator::ValidationError;
+use surrealdb_types::RecordId;
+use validator::{Validate, ValidateUrl, ValidationError};
 
 use crate::{
-    AppResult, DB,
-    common_service::validate_image,
-    db::{parse_thing, parse_thing_to_record},
+    AppResult, DB, Store, Views,
+    db::{parse_cursor, parse_thing, parse_thing_to_record},
     error::AppError,
+    hashtags::hashtag_service,
+    insights::model::ViewSource,
     jwt::AuthUser,
+    media::{image_service, store::MediaStore, transcode_service, video_service},
+    notifications::notification_service,
     posts::model::{
-        FeedQuery, Like, LikeResponse, Post, PostFormRequest, PostRequest, PostResponse,
+        AuthorSummary, EditPostRequest, FeedQuery, MAX_CAPTION_LEN, MAX_MEDIA_ITEMS,
+        MAX_PINNED_POSTS, MAX_TEXT_POST_LEN, Media, MediaKind, NewPost, Poll, Post, PostEdit,
+        PostEditResponse, PostFormRequest, PostGridItemResponse, PostKind, PostRequest,
+        PostResponse, PostStatus, PostVisibility, Reaction, Reactor, ReactorResponse,
     },
+    posts::{enrich_service, poll_service, timeline_service},
+    users::{mention_service, user_service::is_blocked_between},
 };
 
 #[post("/post", data = "<form>", format = "multipart/form-data")]
 pub async fn post(
     mut form: Form<PostFormRequest<'_>>,
     db: &State<DB>,
+    store: &State<Store>,
     auth: AuthUser,
 ) -> AppResult<Json<PostResponse>> {
-    let caption = form.caption.clone();
-    let file = &mut form.content;
-    let filename = format!("{}.png", Uuid::new_v4());
-    let path = format!("data/posts/{}", filename);
-    let content_type =
-        file.content_type()
-            .ok_or(AppError::ValidationError(ValidationError::new(
-                "Missing content type",
-            )))?;
-
-    if !validate_image(content_type) {
+    let uid = parse_thing(&auth.user_id)?;
+    let kind = resolve_post_kind(&form)?;
+    let (_, owner) = parse_thing_to_record(&auth.user_id)?;
+    let poll = poll_service::resolve_poll(&form)?;
+    let media = save_form_media(db, store.inner().as_ref(), &owner, &mut form).await?;
+    let post = publish_post(
+        db,
+        &uid,
+        NewPost {
+            kind,
+            media,
+            caption: form.caption.trim().to_string(),
+            link_url: form.link_url.as_ref().map(|url| url.trim().to_string()),
+            visibility: form.visibility.unwrap_or_default(),
+            poll,
+            draft: None,
+        },
+    )
+    .await?;
+    let post = enrich_service::enrich_post(db, &uid, post).await?;
+    Ok(Json(post))
+}
+
+/// Creates a post from already stored media and runs what follows a new
+/// post: tag indexing, then feed fan-out and mention notifications, or a
+/// transcode when the post carries video.
+pub async fn publish_post(db: &DB, uid: &RecordId, post: NewPost) -> AppResult<Post> {
+    let NewPost {
+        kind,
+        media,
+        caption,
+        link_url,
+        visibility,
+        poll,
+        draft,
+    } = post;
+    if poll.as_ref().is_some_and(Poll::is_closed) {
         return Err(AppError::ValidationError(ValidationError::new(
-            "File must be an image",
+            "Poll has already ended",
         )));
     }
-    file.persist_to(&path).await?;
-    let url = format!("http://localhost:8080/posts/{}", filename);
+    let mentions = mention_service::resolve_mentions(db, uid, &caption).await?;
+    let status = if media.iter().any(|m| m.kind == MediaKind::Video) {
+        PostStatus::Processing
+    } else {
+        PostStatus::Ready
+    };
     let mut res = db
-        .create("posts")
-        .content(PostRequest {
-            caption: caption,
-            content: url,
-            uid: parse_thing(&auth.user_id)?,
-        })
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $created = CREATE ONLY posts CONTENT $post;
+            IF $draft != NONE {
+                DELETE $draft;
+            };
+            RETURN $created;
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind((
+            "post",
+            PostRequest {
+                kind,
+                caption,
+                mentions,
+                media,
+                link_url,
+                status,
+                visibility,
+                uid: uid.clone(),
+                original: None,
+                poll,
+            },
+        ))
+        .bind(("draft", draft))
         .await?;
-    let post: Post = res
-        .take()
+    let post = res
+        .take::<Option<Post>>(2)?
         .ok_or(AppError::XCustomMessage("Failed to post"))?;
-    Ok(Json(post.into()))
+    hashtag_service::sync_post_tags(db, &post.id, &post.caption).await?;
+    if post.status == PostStatus::Ready {
+        timeline_service::fan_out_post(db, &post).await?;
+        notification_service::sync_mentions(db, uid, &post, None, &post.mentions).await?;
+    } else {
+        transcode_service::enqueue(db, &post.id, &post.media).await?;
+    }
+    Ok(post)
 }
 
-#[get("/get-user-posts")]
-pub async fn get_user_posts(db: &State<DB>, auth: AuthUser) -> AppResult<Json<Vec<PostResponse>>> {
-    let res: Vec<Post> = db
-        .query("SELECT * FROM posts WHERE uid=$uid ORDER BY created_at DESC")
-        .bind(("uid", parse_thing(&auth.user_id)?))
-        .await?
-        .take::<Vec<Post>>(0)?;
-    let mut posts: Vec<PostResponse> = res.into_iter().map(Into::into).collect();
-    for e in posts.iter_mut() {
-        let liked = liked_by_user(db, &auth.user_id, &e.id).await?;
-        e.liked_by_user = liked;
+/// Stores the files of a post form under `posts/<owner>/`, pairing them
+/// with their alt texts. `owner` is the key part of the author's id. Nothing
+/// is kept if any file fails.
+pub async fn save_form_media(
+    db: &DB,
+    store: &dyn MediaStore,
+    owner: &str,
+    form: &mut PostFormRequest<'_>,
+) -> AppResult<Vec<Media>> {
+    let dir = format!("posts/{}", owner);
+    let mut alt_texts = std::mem::take(&mut form.alt_text).into_iter();
+    let mut media = Vec::with_capacity(form.content.len());
+    for (position, file) in form.content.iter().enumerate() {
+        let alt_text = alt_texts.next().filter(|alt| !alt.trim().is_empty());
+        match save_media(db, store, &dir, file, position, alt_text).await {
+            Ok(item) => media.push(item),
+            Err(e) => {
+                release_media(db, store, &media).await?;
+                return Err(e);
+            }
+        }
     }
-    Ok(Json(posts))
+    Ok(media)
 }
 
-#[get("/feed?<q..>")]
-pub async fn get_feed(
+/// Checks the submitted fields against the post kind's rules and returns
+/// the kind to store.
+pub fn resolve_post_kind(form: &PostFormRequest<'_>) -> AppResult<PostKind> {
+    let kind = form.kind.unwrap_or(if form.link_url.is_some() {
+        PostKind::Link
+    } else if form.content.is_empty() {
+        PostKind::Text
+    } else {
+        PostKind::Image
+    });
+    let caption_len = form.caption.trim().chars().count();
+    if form.content.len() > MAX_MEDIA_ITEMS {
+        return Err(AppError::ValidationError(
+            ValidationError::new("too_many_media_items").with_message(
+                format!("A post can have at most {} media items", MAX_MEDIA_ITEMS).into(),
+            ),
+        ));
+    }
+    match kind {
+        PostKind::Image => {
+            if form.content.is_empty() {
+                return Err(AppError::ValidationError(ValidationError::new(
+                    "Image posts need at least one media item",
+                )));
+            }
+            if caption_len > MAX_CAPTION_LEN {
+                return Err(AppError::ValidationError(ValidationError::new(
+                    "Caption must be at most 2200 characters",
+                )));
+            }
+        }
+        PostKind::Text => {
+            if !form.content.is_empty() {
+                return Err(AppError::ValidationError(ValidationError::new(
+                    "Text posts cannot carry media",
+                )));
+            }
+            if caption_len == 0 || caption_len > MAX_TEXT_POST_LEN {
+                return Err(AppError::ValidationError(ValidationError::new(
+                    "Text posts must be 1-5000 characters",
+                )));
+            }
+        }
+        PostKind::Link => {
+            let url = form.link_url.as_deref().map(str::trim).unwrap_or_default();
+            if !(url.starts_with("http://") || url.starts_with("https://")) || !url.validate_url() {
+                return Err(AppError::ValidationError(ValidationError::new(
+                    "Link posts need a valid http(s) URL",
+                )));
+            }
+            if caption_len > MAX_CAPTION_LEN {
+                return Err(AppError::ValidationError(ValidationError::new(
+                    "Caption must be at most 2200 characters",
+                )));
+            }
+        }
+        PostKind::Repost | PostKind::Quote => {
+            return Err(AppError::ValidationError(ValidationError::new(
+                "Reposts and quotes are created from the original post",
+            )));
+        }
+    }
+    if kind != PostKind::Link && form.link_url.is_some() {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Only link posts can carry a URL",
+        )));
+    }
+    Ok(kind)
+}
+
+/// Checks an edited caption against the post kind's length rules.
+pub fn check_caption(kind: PostKind, caption: &str) -> AppResult<()> {
+    let caption_len = caption.trim().chars().count();
+    if kind == PostKind::Text && caption_len == 0 {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Text posts must be 1-5000 characters",
+        )));
+    }
+    if kind == PostKind::Quote && caption_len == 0 {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Caption must be 1-2200 characters",
+        )));
+    }
+    if kind != PostKind::Text && caption_len > MAX_CAPTION_LEN {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Caption must be at most 2200 characters",
+        )));
+    }
+    Ok(())
+}
+
+/// Stores one uploaded file under `<dir>/`. Images are processed into
+/// renditions right away; videos are stored as-is and transcoded by the
+/// background worker.
+pub async fn save_media(
+    db: &DB,
+    store: &dyn MediaStore,
+    dir: &str,
+    file: &TempFile<'_>,
+    position: usize,
+    alt_text: Option<String>,
+) -> AppResult<Media> {
+    let bytes = image_service::read_upload(file).await?;
+    if let Some(format) = video_service::sniff(&bytes) {
+        let storage_key = video_service::store_source(store, bytes, format, dir).await?;
+        return Ok(Media {
+            position,
+            kind: MediaKind::Video,
+            storage_key,
+            width: None,
+            height: None,
+            alt_text,
+            renditions: Vec::new(),
+            poster: Vec::new(),
+            placeholder: None,
+        });
+    }
+    let image = image_service::store_image_bytes(db, store, bytes, dir).await?;
+    let full = image.full();
+    Ok(Media {
+        position,
+        kind: MediaKind::Image,
+        storage_key: full.storage_key.clone(),
+        width: Some(full.width),
+        height: Some(full.height),
+        alt_text,
+        renditions: image.renditions,
+        poster: Vec::new(),
+        placeholder: image.placeholder,
+    })
+}
+
+/// The caller's posts, pinned ones first (most recently pinned first), then
+/// the rest newest first. Archived posts are only included on request.
+#[get("/get-user-posts?<include_archived>")]
+pub async fn get_user_posts(
+    include_archived: Option<bool>,
     db: &State<DB>,
     auth: AuthUser,
-    q: FeedQuery,
 ) -> AppResult<Json<Vec<PostResponse>>> {
-    let page = q.page.unwrap_or(1);
-    let limit = q.limit.unwrap_or(10);
-    let start = (page - 1) * limit;
+    let uid = parse_thing(&auth.user_id)?;
     let res: Vec<Post> = db
         .query(
             "
-        SELECT * FROM posts
-        WHERE uid IN (
-            SELECT VALUE following_id
-            FROM follows
-            WHERE follower_id = $uid
-        )
-        ORDER BY created_at DESC
-        LIMIT $limit
-        START $offset;
-        ",
+            SELECT * FROM posts
+            WHERE uid = $uid
+            AND ($archived OR archived != true)
+            ORDER BY pinned_at DESC, created_at DESC
+            ",
         )
-        .bind(("uid", parse_thing(&auth.user_id)?))
-        .bind(("limit", limit))
-        .bind(("offset", start))
+        .bind(("uid", uid.clone()))
+        .bind(("archived", include_archived.unwrap_or(false)))
         .await?
-        .take(0)?;
-    let mut posts: Vec<PostResponse> = res.into_iter().map(Into::into).collect();
-    for e in posts.iter_mut() {
-        let liked = liked_by_user(db, &auth.user_id, &e.id).await?;
-        e.liked_by_user = liked;
+        .take::<Vec<Post>>(0)?;
+    let posts = enrich_service::enrich_posts(db, &uid, res).await?;
+    Ok(Json(posts))
+}
+
+/// Conditions shared by both halves of a profile grid: no reposts, no
+/// archived posts, only finished posts unless `$own`, and only the
+/// visibilities in `$allowed`.
+const GRID_FILTER: &str = "
+    uid = $uid
+    AND archived != true
+    AND (kind ?? 'image') != 'repost'
+    AND ($own OR (status ?? 'ready') = 'ready')
+    AND (visibility ?? 'public') IN $allowed
+";
+
+/// Any user's posts as lightweight grid items, pinned posts first, then
+/// newest first. `cursor` is the `cursor` of the last unpinned item seen;
+/// pinned posts only come with the first page. Each post is only listed
+/// for its audience, so a private account shows followers its posts and
+/// close friends only the posts shared with them. Mounted with the user
+/// routes.
+#[get("/users/<id>/posts?<cursor>&<limit>")]
+pub async fn get_user_grid(
+    id: &str,
+    cursor: Option<String>,
+    limit: Option<u32>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<PostGridItemResponse>>> {
+    let viewer = parse_thing(&auth.user_id)?;
+    let uid = parse_thing(id)?;
+    let own = viewer == uid;
+    let limit = limit.unwrap_or(24).min(100);
+    let (cursor_at, cursor_id) = cursor.as_deref().map(parse_cursor).transpose()?.unzip();
+    let mut res = db
+        .query(
+            "
+            SELECT id, username, profile_picture, is_private FROM ONLY $uid;
+            SELECT VALUE id FROM follows WHERE follower_id = $viewer AND following_id = $uid;
+            SELECT VALUE id FROM close_friends WHERE in = $uid AND out = $viewer;
+            ",
+        )
+        .bind(("uid", uid.clone()))
+        .bind(("viewer", viewer.clone()))
+        .await?;
+    let author = res
+        .take::<Option<AuthorSummary>>(0)?
+        .ok_or(AppError::XCustomMessage("User not found"))?;
+    let following = !res.take::<Vec<RecordId>>(1)?.is_empty();
+    let friend = !res.take::<Vec<RecordId>>(2)?.is_empty();
+    if !own && is_blocked_between(db, &viewer, &uid).await? {
+        return Err(AppError::XCustomMessage("User not found"));
+    }
+    let mut allowed = Vec::new();
+    if own || following || !author.is_private {
+        allowed.push(PostVisibility::Public);
+    }
+    if own || following {
+        allowed.push(PostVisibility::Followers);
+    }
+    if own || friend {
+        allowed.push(PostVisibility::Friends);
     }
+    if own {
+        allowed.push(PostVisibility::Me);
+    }
+    if allowed.is_empty() {
+        return Err(AppError::Forbidden("This account is private"));
+    }
+    let mut res = db
+        .query(format!(
+            "
+            IF $cursor_at = NONE {{
+                SELECT * FROM posts WHERE {GRID_FILTER} AND pinned_at != NONE
+                ORDER BY pinned_at DESC
+            }} ELSE {{
+                []
+            }};
+            SELECT * FROM posts
+                WHERE {GRID_FILTER}
+                AND pinned_at = NONE
+                AND (
+                    $cursor_at = NONE
+                    OR created_at < $cursor_at
+                    OR (created_at = $cursor_at AND id < $cursor_id)
+                )
+                ORDER BY created_at DESC, id DESC
+                LIMIT $limit;
+            "
+        ))
+        .bind(("uid", uid))
+        .bind(("own", own))
+        .bind(("allowed", allowed))
+        .bind(("cursor_at", cursor_at))
+        .bind(("cursor_id", cursor_id))
+        .bind(("limit", limit))
+        .await?;
+    let mut posts = res.take::<Vec<Post>>(0)?;
+    posts.extend(res.take::<Vec<Post>>(1)?);
+    let items = posts
+        .into_iter()
+        .map(|post| {
+            let restricted = author.is_private || post.visibility != PostVisibility::Public;
+            let mut item: PostGridItemResponse = post.into();
+            if restricted && let Some(thumbnail) = item.thumbnail.as_mut() {
+                thumbnail.sign();
+            }
+            if !own {
+                item.views_count = None;
+            }
+            item
+        })
+        .collect();
+    Ok(Json(items))
+}
+
+#[get("/feed?<q..>")]
+pub async fn get_feed(
+    db: &State<DB>,
+    views: &State<Views>,
+    auth: AuthUser,
+    q: FeedQuery,
+) -> AppResult<Json<Vec<PostResponse>>> {
+    let page = q.page.unwrap_or(1).max(1);
+    let limit = q.limit.unwrap_or(10).min(50);
+    let start = (page - 1).saturating_mul(limit);
+    let uid = parse_thing(&auth.user_id)?;
+    let res = timeline_service::read_feed(db, uid.clone(), limit, start).await?;
+    let posts = enrich_service::enrich_posts(db, &uid, res).await?;
+    views.record(&uid, &posts, ViewSource::Feed);
     Ok(Json(posts))
 }
 
@@ -106,90 +440,400 @@ pub async fn get_feed(
 pub async fn get_post_by_id(
     id: &str,
     db: &State<DB>,
+    views: &State<Views>,
     auth: AuthUser,
 ) -> AppResult<Json<PostResponse>> {
     let res: Post = db
         .select(parse_thing_to_record(id)?)
         .await?
         .ok_or(AppError::XCustomMessage("Post not found"))?;
-    let mut post: PostResponse = res.into();
-    let liked = liked_by_user(db, &auth.user_id, id).await?;
-    post.liked_by_user = liked;
+    let uid = parse_thing(&auth.user_id)?;
+    let post = enrich_service::enrich_post(db, &uid, res).await?;
+    views.record(&uid, std::slice::from_ref(&post), ViewSource::Post);
     Ok(Json(post))
 }
 
-async fn liked_by_user(db: &State<DB>, uid: &str, pid: &str) -> AppResult<bool> {
-    let res = db
+#[put("/like-post/<id>?<reaction>")]
+pub async fn like_post(
+    id: &str,
+    reaction: Option<form::Result<'_, Reaction>>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<String> {
+    let reaction = parse_reaction(reaction)?.unwrap_or(Reaction::Like);
+    let uid = parse_thing(&auth.user_id)?;
+    let post = find_post(db, parse_thing(id)?).await?;
+    if is_blocked_between(db, &uid, &post.uid).await? {
+        return Err(AppError::Forbidden("Cannot react to this post"));
+    }
+    enrich_service::ensure_visible(db, &uid, &post).await?;
+    let pid = post.id;
+    let mut res = db
         .query(
             "
-        SELECT VALUE $user_id INSIDE user_ids
-        FROM likes
-        WHERE post_id = $post_id
-        LIMIT 1;
+            BEGIN TRANSACTION;
+
+            LET $row = (SELECT * FROM reactions WHERE in = $uid AND out = $pid LIMIT 1)[0];
+            IF $row = NONE {
+                RELATE $uid->reactions->$pid SET
+                    reaction = $reaction,
+                    created_at = time::now();
+                UPDATE $pid SET likes_count += 1;
+            } ELSE IF $row.reaction = $reaction {
+                DELETE $row.id;
+                UPDATE $pid SET likes_count -= 1;
+            } ELSE {
+                UPDATE $row.id SET reaction = $reaction, created_at = time::now();
+            };
+            SELECT VALUE reaction FROM reactions WHERE in = $uid AND out = $pid;
+            COMMIT TRANSACTION;
         ",
         )
-        .bind(("user_id", parse_thing(uid)?))
-        .bind(("post_id", parse_thing(pid)?))
-        .await?
-        .take::<Option<bool>>(0)?;
-    if res.is_some() {
-        Ok(res.unwrap())
-    } else {
-        Ok(false)
+        .bind(("uid", uid))
+        .bind(("pid", pid))
+        .bind(("reaction", reaction))
+        .await?;
+    let current = res.take::<Option<Reaction>>(2)?;
+    match current {
+        Some(Reaction::Like) => Ok("Liked the post".to_string()),
+        Some(_) => Ok("Reacted to the post".to_string()),
+        None => Ok("Unliked the post".to_string()),
     }
 }
 
-#[put("/like-post/<id>")]
-pub async fn like_post(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+#[get("/get-reactions/<id>?<reaction>&<cursor>&<limit>")]
+pub async fn get_reactions(
+    id: &str,
+    reaction: Option<form::Result<'_, Reaction>>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<ReactorResponse>>> {
+    let reaction = parse_reaction(reaction)?;
+    let post = find_post(db, parse_thing(id)?).await?;
+    enrich_service::ensure_visible(db, &parse_thing(&auth.user_id)?, &post).await?;
+    let limit = limit.unwrap_or(20).min(100);
+    let (cursor_at, cursor_id) = cursor.as_deref().map(parse_cursor).transpose()?.unzip();
+    let res: Vec<Reactor> = db
+        .query(
+            "
+            SELECT
+                in.{ id, username, profile_picture } AS user,
+                reaction,
+                created_at
+            FROM reactions
+            WHERE out = $pid
+            AND ($reaction = NONE OR reaction = $reaction)
+            AND (
+                $cursor_at = NONE
+                OR created_at < $cursor_at
+                OR (created_at = $cursor_at AND in < $cursor_id)
+            )
+            ORDER BY created_at DESC, in DESC
+            LIMIT $limit
+            ",
+        )
+        .bind(("pid", post.id))
+        .bind(("reaction", reaction))
+        .bind(("cursor_at", cursor_at))
+        .bind(("cursor_id", cursor_id))
+        .bind(("limit", limit))
+        .await?
+        .take(0)?;
+    Ok(Json(res.into_iter().map(Into::into).collect()))
+}
+
+/// An omitted `reaction` is `None`; an unknown one is rejected rather than
+/// read as a like.
+fn parse_reaction(reaction: Option<form::Result<'_, Reaction>>) -> AppResult<Option<Reaction>> {
+    reaction
+        .transpose()
+        .map_err(|_| AppError::ValidationError(ValidationError::new("Invalid reaction")))
+}
+
+#[put("/edit-post/<id>", data = "<req>")]
+pub async fn edit_post(
+    id: &str,
+    req: Json<EditPostRequest>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<PostResponse>> {
+    req.validate()?;
     let uid = parse_thing(&auth.user_id)?;
-    let pid = parse_thing(id)?;
+    let post = find_post(db, parse_thing(id)?).await?;
+    if post.uid != uid {
+        return Err(AppError::Forbidden("Only the owner can edit a post"));
+    }
+    if post.kind == PostKind::Repost {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Reposts have no caption to edit",
+        )));
+    }
+    check_caption(post.kind, &req.caption)?;
     let mut res = db
         .query(
             "
             BEGIN TRANSACTION;
+            CREATE post_edits SET
+                post = $pid,
+                caption = $pid.caption,
+                edited_at = time::now();
+            UPDATE $pid SET
+                caption = $caption,
+                mentions = $mentions,
+                edited_at = time::now();
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("pid", post.id))
+        .bind(("caption", req.caption.clone()))
+        .bind((
+            "mentions",
+            mention_service::resolve_mentions(db, &uid, &req.caption).await?,
+        ))
+        .await?;
+    let post = res
+        .take::<Option<Post>>(1)?
+        .ok_or(AppError::XCustomMessage("Post not found"))?;
+    hashtag_service::sync_post_tags(db, &post.id, &post.caption).await?;
+    if post.status == PostStatus::Ready {
+        notification_service::sync_mentions(db, &uid, &post, None, &post.mentions).await?;
+    }
+    let post = enrich_service::enrich_post(db, &uid, post).await?;
+    Ok(Json(post))
+}
 
-            LET $rec = SELECT * FROM likes WHERE post_id=$pid LIMIT 1;
-            LET $row = $rec[0];
-            IF $row = NONE {
-                CREATE likes SET
-                    post_id = $pid,
-                    user_ids += $uid;
-                UPDATE $pid SET likes_count += 1;
-            } ELSE {
-                LET $users = IF $row.user_ids = NONE THEN [] ELSE $row.user_ids END;
-                IF $uid INSIDE $users  {
-                    UPDATE $row.id SET user_ids -= $uid;
-                    UPDATE $pid SET likes_count -= 1;
-                } ELSE {
-                    UPDATE $row.id SET user_ids += $uid;
-                    UPDATE $pid SET likes_count += 1;
-                };
+/// Changes who can see a post and rebuilds its timeline entries for the new
+/// audience.
+#[put("/update-visibility/<id>?<visibility>")]
+pub async fn update_visibility(
+    id: &str,
+    visibility: PostVisibility,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<PostResponse>> {
+    let uid = parse_thing(&auth.user_id)?;
+    let post = find_post(db, parse_thing(id)?).await?;
+    if post.uid != uid {
+        return Err(AppError::Forbidden("Only the owner can change visibility"));
+    }
+    if post.kind == PostKind::Repost {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Reposts are always public",
+        )));
+    }
+    let post = db
+        .query("UPDATE $pid SET visibility = $visibility RETURN AFTER")
+        .bind(("pid", post.id))
+        .bind(("visibility", visibility))
+        .await?
+        .take::<Option<Post>>(0)?
+        .ok_or(AppError::XCustomMessage("Post not found"))?;
+    if post.status == PostStatus::Ready {
+        timeline_service::retract_post(db, &post.id).await?;
+        timeline_service::fan_out_post(db, &post).await?;
+    }
+    let post = enrich_service::enrich_post(db, &uid, post).await?;
+    Ok(Json(post))
+}
 
+/// Pins a post to the top of the caller's profile, up to
+/// [`MAX_PINNED_POSTS`].
+#[put("/pin-post/<id>")]
+pub async fn pin_post(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let post = find_own_post(db, parse_thing(id)?, &uid).await?;
+    if post.archived {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Archived posts cannot be pinned",
+        )));
+    }
+    if post.pinned_at.is_some() {
+        return Ok("Post already pinned".to_string());
+    }
+    // Counting and pinning in one transaction keeps concurrent pins from
+    // going over the limit.
+    let mut res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $full = array::len(
+                SELECT VALUE id FROM posts WHERE uid = $uid AND pinned_at != NONE
+            ) >= $max;
+            IF !$full {
+                UPDATE $pid SET pinned_at = time::now() WHERE pinned_at = NONE;
             };
-            SELECT * FROM likes WHERE post_id=$pid;
+            RETURN $full;
             COMMIT TRANSACTION;
-        ",
+            ",
         )
-        .bind(("uid", uid.clone()))
-        .bind(("pid", pid))
+        .bind(("uid", uid))
+        .bind(("pid", post.id))
+        .bind(("max", MAX_PINNED_POSTS))
         .await?;
-    let like = res
-        .take::<Option<Like>>(3)?
-        .ok_or(AppError::XCustomMessage("Error occured"))?;
-    if like.user_ids.contains(&uid) {
-        return Ok(format!("Liked the post"));
-    } else {
-        return Ok(format!("Unliked the post"));
+    if res.take::<Option<bool>>(2)?.unwrap_or(false) {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "At most 3 posts can be pinned",
+        )));
     }
+    Ok("Post pinned".to_string())
+}
+
+#[delete("/unpin-post/<id>")]
+pub async fn unpin_post(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let post = find_own_post(db, parse_thing(id)?, &uid).await?;
+    let res = db
+        .query("UPDATE $pid SET pinned_at = NONE")
+        .bind(("pid", post.id))
+        .await?;
+    res.check()?;
+    Ok("Post unpinned".to_string())
 }
 
-#[get("/get-likes/<id>")]
-pub async fn get_likes(id: &str, db: &State<DB>) -> AppResult<Json<Option<LikeResponse>>> {
+/// Hides a post from everyone but its author. It is unpinned and taken out
+/// of every feed, but keeps its reactions, comments and bookmarks.
+#[put("/archive-post/<id>")]
+pub async fn archive_post(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let post = find_own_post(db, parse_thing(id)?, &uid).await?;
+    if post.kind == PostKind::Repost {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Reposts cannot be archived",
+        )));
+    }
     let res = db
-        .query("SELECT * FROM likes WHERE post_id=$pid")
-        .bind(("pid", parse_thing(id)?))
+        .query("UPDATE $pid SET archived = true, pinned_at = NONE")
+        .bind(("pid", post.id.clone()))
+        .await?;
+    res.check()?;
+    timeline_service::retract_post(db, &post.id).await?;
+    Ok("Post archived".to_string())
+}
+
+/// Brings an archived post back to the profile and to followers' feeds.
+#[put("/unarchive-post/<id>")]
+pub async fn unarchive_post(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let post = find_own_post(db, parse_thing(id)?, &uid).await?;
+    if !post.archived {
+        return Ok("Post is not archived".to_string());
+    }
+    let post = db
+        .query("UPDATE $pid SET archived = false RETURN AFTER")
+        .bind(("pid", post.id))
         .await?
-        .take::<Vec<Like>>(0)?;
-    let like = res.into_iter().next().map(LikeResponse::from);
-    Ok(Json(like))
+        .take::<Option<Post>>(0)?
+        .ok_or(AppError::XCustomMessage("Post not found"))?;
+    if post.status == PostStatus::Ready {
+        timeline_service::fan_out_post(db, &post).await?;
+    }
+    Ok("Post unarchived".to_string())
+}
+
+#[get("/get-post-edits/<id>")]
+pub async fn get_post_edits(
+    id: &str,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<PostEditResponse>>> {
+    let post = find_post(db, parse_thing(id)?).await?;
+    enrich_service::ensure_visible(db, &parse_thing(&auth.user_id)?, &post).await?;
+    let edits: Vec<PostEdit> = db
+        .query("SELECT * FROM post_edits WHERE post = $pid ORDER BY edited_at DESC")
+        .bind(("pid", post.id))
+        .await?
+        .take(0)?;
+    Ok(Json(edits.into_iter().map(Into::into).collect()))
+}
+
+#[delete("/delete-post/<id>")]
+pub async fn delete_post(
+    id: &str,
+    db: &State<DB>,
+    store: &State<Store>,
+    auth: AuthUser,
+) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let post = find_post(db, parse_thing(id)?).await?;
+    if post.uid != uid {
+        return Err(AppError::Forbidden("Only the owner can delete a post"));
+    }
+    remove_post(db, store.inner().as_ref(), post).await?;
+    Ok("Post deleted".to_string())
+}
+
+/// Deletes a post with everything hanging off it, together with the pure
+/// reposts of it. Quotes are kept and show the original as unavailable.
+pub async fn remove_post(db: &DB, store: &dyn MediaStore, post: Post) -> AppResult<()> {
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $ids = array::concat(
+                [$pid],
+                SELECT VALUE id FROM posts WHERE original = $pid AND kind = 'repost'
+            );
+            LET $comments = SELECT VALUE id FROM comments WHERE post IN $ids;
+            DELETE comment_likes WHERE out IN $comments;
+            DELETE comments WHERE post IN $ids;
+            DELETE reactions WHERE out IN $ids;
+            DELETE poll_votes WHERE out = $pid;
+            DELETE post_views WHERE post = $pid;
+            DELETE trending_posts WHERE post = $pid;
+            DELETE engagement_snapshots WHERE post = $pid;
+            DELETE bookmarks WHERE out IN $ids;
+            DELETE post_edits WHERE post = $pid;
+            DELETE timeline WHERE post IN $ids;
+            DELETE transcode_jobs WHERE post = $pid;
+            UPDATE (SELECT VALUE out FROM tagged WHERE in = $pid) SET posts_count -= 1;
+            DELETE tagged WHERE in = $pid;
+            DELETE notifications WHERE post IN $ids;
+            IF $kind = 'repost' {
+                UPDATE $original SET reposts_count -= 1;
+            };
+            DELETE $ids;
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("pid", post.id))
+        .bind(("kind", post.kind))
+        .bind(("original", post.original))
+        .await?;
+    res.check()?;
+    release_media(db, store, &post.media).await
+}
+
+pub async fn find_post(db: &DB, id: RecordId) -> AppResult<Post> {
+    db.query("SELECT * FROM ONLY $id")
+        .bind(("id", id))
+        .await?
+        .take::<Option<Post>>(0)?
+        .ok_or(AppError::XCustomMessage("Post not found"))
+}
+
+async fn find_own_post(db: &DB, id: RecordId, owner: &RecordId) -> AppResult<Post> {
+    let post = find_post(db, id).await?;
+    if post.uid != *owner {
+        return Err(AppError::Forbidden("Not your post"));
+    }
+    Ok(post)
+}
+
+/// Lets go of the stored objects behind media items. Images are shared
+/// content-addressed blobs and are only released; videos belong to a single
+/// post or story and are deleted right away.
+pub async fn release_media(db: &DB, store: &dyn MediaStore, media: &[Media]) -> AppResult<()> {
+    for item in media {
+        match item.kind {
+            MediaKind::Image => image_service::release(db, &item.renditions).await?,
+            MediaKind::Video => {
+                store.delete(&item.storage_key).await?;
+                image_service::remove_renditions(store, &item.renditions).await?;
+            }
+        }
+        image_service::release(db, &item.poster).await?;
+    }
+    Ok(())
 }
@@ -0,0 +1,199 @@
+use rocket::{State, delete, post, serde::json::Json};
+use surrealdb_types::RecordId;
+use validator::{Validate, ValidationError};
+
+use crate::{
+    AppResult, DB, Store,
+    db::parse_thing,
+    error::AppError,
+    hashtags::hashtag_service,
+    jwt::AuthUser,
+    notifications::{model::NotificationKind, notification_service},
+    posts::{
+        enrich_service,
+        model::{
+            Post, PostKind, PostRequest, PostResponse, PostStatus, PostVisibility, QuoteRequest,
+        },
+        post_service::{find_post, remove_post},
+        timeline_service,
+    },
+    users::{mention_service, user_service::is_blocked_between},
+};
+
+/// Boosts a post to the caller's followers. Reposting a repost shares its
+/// original, and each account can repost a post once.
+#[post("/repost/<id>")]
+pub async fn repost(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<Json<PostResponse>> {
+    let uid = parse_thing(&auth.user_id)?;
+    let original = find_shareable(db, &uid, parse_thing(id)?).await?;
+    // The check runs in the transaction that creates the repost, so two
+    // concurrent requests cannot both pass it.
+    let mut res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $existing = (SELECT VALUE id FROM posts
+                WHERE original = $original AND uid = $uid AND kind = 'repost'
+                LIMIT 1)[0];
+            LET $created = IF $existing = NONE THEN (CREATE ONLY posts CONTENT $post) END;
+            IF $created != NONE {
+                UPDATE $original SET reposts_count += 1;
+            };
+            RETURN $created;
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind((
+            "post",
+            PostRequest {
+                kind: PostKind::Repost,
+                media: Vec::new(),
+                caption: String::new(),
+                mentions: Vec::new(),
+                link_url: None,
+                status: PostStatus::Ready,
+                visibility: PostVisibility::Public,
+                uid: uid.clone(),
+                original: Some(original.id.clone()),
+                poll: None,
+            },
+        ))
+        .bind(("original", original.id.clone()))
+        .bind(("uid", uid.clone()))
+        .await?;
+    let post = res
+        .take::<Option<Post>>(3)?
+        .ok_or(AppError::ValidationError(ValidationError::new(
+            "Post already reposted",
+        )))?;
+    timeline_service::fan_out_post(db, &post).await?;
+    notification_service::notify(db, &original.uid, &uid, NotificationKind::Repost, &post.id)
+        .await?;
+    let post = enrich_service::enrich_post(db, &uid, post).await?;
+    Ok(Json(post))
+}
+
+/// Takes back the caller's repost of `id`, which may be the original or the
+/// repost itself.
+#[delete("/undo-repost/<id>")]
+pub async fn undo_repost(
+    id: &str,
+    db: &State<DB>,
+    store: &State<Store>,
+    auth: AuthUser,
+) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let id = parse_thing(id)?;
+    let repost = match find_repost(db, &uid, &id).await? {
+        Some(repost) => repost,
+        None => {
+            let post = find_post(db, id).await?;
+            if post.kind != PostKind::Repost || post.uid != uid {
+                return Err(AppError::XCustomMessage("Repost not found"));
+            }
+            post
+        }
+    };
+    remove_post(db, store.inner().as_ref(), repost).await?;
+    Ok("Repost removed".to_string())
+}
+
+/// Shares a post with a caption of the caller's own. Quoting a repost
+/// quotes its original.
+#[post("/quote/<id>", data = "<req>")]
+pub async fn quote_post(
+    id: &str,
+    req: Json<QuoteRequest>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<PostResponse>> {
+    req.validate()?;
+    let uid = parse_thing(&auth.user_id)?;
+    let caption = req.caption.trim().to_string();
+    if caption.is_empty() {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Caption must be 1-2200 characters",
+        )));
+    }
+    let original = find_shareable(db, &uid, parse_thing(id)?).await?;
+    let mentions = mention_service::resolve_mentions(db, &uid, &caption).await?;
+    let mut res = db
+        .create("posts")
+        .content(PostRequest {
+            kind: PostKind::Quote,
+            media: Vec::new(),
+            caption,
+            mentions,
+            link_url: None,
+            status: PostStatus::Ready,
+            visibility: req.visibility.unwrap_or_default(),
+            uid: uid.clone(),
+            original: Some(original.id.clone()),
+            poll: None,
+        })
+        .await?;
+    let post: Post = res
+        .take()
+        .ok_or(AppError::XCustomMessage("Failed to quote"))?;
+    hashtag_service::sync_post_tags(db, &post.id, &post.caption).await?;
+    timeline_service::fan_out_post(db, &post).await?;
+    notification_service::sync_mentions(db, &uid, &post, None, &post.mentions).await?;
+    notification_service::notify(db, &original.uid, &uid, NotificationKind::Quote, &post.id)
+        .await?;
+    let post = enrich_service::enrich_post(db, &uid, post).await?;
+    Ok(Json(post))
+}
+
+/// Resolves the post that sharing `id` would point at, following reposts to
+/// their original. Unfinished and archived posts, other people's non-public posts, posts
+/// of private accounts and posts of accounts blocked in either direction
+/// cannot be shared.
+async fn find_shareable(db: &DB, sharer: &RecordId, id: RecordId) -> AppResult<Post> {
+    let mut post = find_post(db, id).await?;
+    if post.kind == PostKind::Repost {
+        let original = post
+            .original
+            .clone()
+            .ok_or(AppError::XCustomMessage("Post not found"))?;
+        post = find_post(db, original).await?;
+    }
+    if post.archived {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Archived posts cannot be shared",
+        )));
+    }
+    if post.status != PostStatus::Ready {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Post is still processing",
+        )));
+    }
+    if post.uid != *sharer {
+        if post.visibility != PostVisibility::Public {
+            return Err(AppError::Forbidden("Only public posts can be shared"));
+        }
+        let private: Option<bool> = db
+            .query("SELECT VALUE is_private FROM ONLY $author")
+            .bind(("author", post.uid.clone()))
+            .await?
+            .take(0)?;
+        if private.unwrap_or(false) {
+            return Err(AppError::Forbidden(
+                "Posts of private accounts cannot be shared",
+            ));
+        }
+        if is_blocked_between(db, sharer, &post.uid).await? {
+            return Err(AppError::Forbidden("Cannot share this post"));
+        }
+    }
+    Ok(post)
+}
+
+async fn find_repost(db: &DB, uid: &RecordId, original: &RecordId) -> AppResult<Option<Post>> {
+    let reposts: Vec<Post> = db
+        .query("SELECT * FROM posts WHERE original = $original AND uid = $uid AND kind = 'repost'")
+        .bind(("original", original.clone()))
+        .bind(("uid", uid.clone()))
+        .await?
+        .take(0)?;
+    Ok(reposts.into_iter().next())
+}
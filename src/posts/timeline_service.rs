@@ -0,0 +1,164 @@
+use surrealdb::{Surreal, engine::remote::ws::Client};
+use surrealdb_types::RecordId;
+
+use crate::{AppResult, DB, posts::model::Post};
+
+/// Authors with more followers than this are not fanned out on write;
+/// their posts are pulled into the feed at read time instead.
+pub const FANOUT_FOLLOWER_LIMIT: i64 = 10_000;
+
+/// Number of recent posts copied into a timeline when a follow is created.
+const BACKFILL_LIMIT: u32 = 50;
+
+/// Pushes a freshly created post into the timeline of every follower of its
+/// author who is in its audience, unless the author is above
+/// [`FANOUT_FOLLOWER_LIMIT`]. Only-me and archived posts are never fanned
+/// out.
+pub async fn fan_out_post(db: &DB, post: &Post) -> AppResult<()> {
+    let res = db
+        .query(
+            "
+            IF $author.followers_count <= $threshold AND $visibility != 'me' AND !$archived {
+                LET $friends = SELECT VALUE out FROM close_friends WHERE in = $author;
+                INSERT INTO timeline (
+                    SELECT
+                        follower_id AS owner,
+                        $post AS post,
+                        $author AS author,
+                        $created_at AS created_at
+                    FROM follows
+                    WHERE following_id = $author
+                    AND ($visibility != 'friends' OR follower_id IN $friends)
+                );
+            };
+            ",
+        )
+        .bind(("post", post.id.clone()))
+        .bind(("author", post.uid.clone()))
+        .bind(("visibility", post.visibility))
+        .bind(("archived", post.archived))
+        .bind(("created_at", post.created_at))
+        .bind(("threshold", FANOUT_FOLLOWER_LIMIT))
+        .await?;
+    res.check()?;
+    Ok(())
+}
+
+/// Copies the most recent posts of `author` into the timeline of `owner`.
+/// Authors above the fan-out threshold are skipped since their posts are
+/// already pulled at read time.
+pub async fn backfill(db: &Surreal<Client>, owner: RecordId, author: RecordId) -> AppResult<()> {
+    let res = db
+        .query(
+            "
+            IF $author.followers_count <= $threshold {
+                LET $friend = (SELECT id FROM close_friends WHERE in = $author AND out = $owner) != [];
+                INSERT INTO timeline (
+                    SELECT
+                        $owner AS owner,
+                        id AS post,
+                        uid AS author,
+                        created_at
+                    FROM posts
+                    WHERE uid = $author
+                    AND (status ?? 'ready') = 'ready'
+                    AND archived != true
+                    AND (
+                        (visibility ?? 'public') IN ['public', 'followers']
+                        OR (visibility = 'friends' AND $friend)
+                    )
+                    ORDER BY created_at DESC
+                    LIMIT $limit
+                );
+            };
+            ",
+        )
+        .bind(("owner", owner))
+        .bind(("author", author))
+        .bind(("threshold", FANOUT_FOLLOWER_LIMIT))
+        .bind(("limit", BACKFILL_LIMIT))
+        .await?;
+    res.check()?;
+    Ok(())
+}
+
+/// Removes a post from every timeline.
+pub async fn retract_post(db: &DB, post: &RecordId) -> AppResult<()> {
+    let res = db
+        .query("DELETE timeline WHERE post = $post")
+        .bind(("post", post.clone()))
+        .await?;
+    res.check()?;
+    Ok(())
+}
+
+/// Removes every entry authored by `author` from the timeline of `owner`.
+pub async fn remove_author(db: &DB, owner: RecordId, author: RecordId) -> AppResult<()> {
+    let res = db
+        .query("DELETE timeline WHERE owner = $owner AND author = $author")
+        .bind(("owner", owner))
+        .bind(("author", author))
+        .await?;
+    res.check()?;
+    Ok(())
+}
+
+/// Reads a page of the feed for `owner`, merging the materialized timeline
+/// with posts pulled from followed accounts above the fan-out threshold and
+/// from followed hashtags.
+pub async fn read_feed(db: &DB, owner: RecordId, limit: u32, offset: u32) -> AppResult<Vec<Post>> {
+    let posts: Vec<Post> = db
+        .query(
+            "
+            LET $pushed = SELECT VALUE post FROM timeline
+                WHERE owner = $uid
+                ORDER BY created_at DESC
+                LIMIT $window;
+            LET $pulled_from = SELECT VALUE following_id FROM follows
+                WHERE follower_id = $uid
+                AND following_id.followers_count > $threshold;
+            LET $close_to = SELECT VALUE in FROM close_friends WHERE out = $uid;
+            LET $pulled = IF array::len($pulled_from) > 0 {
+                SELECT VALUE id FROM posts
+                    WHERE uid IN $pulled_from
+                    AND (status ?? 'ready') = 'ready'
+                    AND archived != true
+                    AND (
+                        (visibility ?? 'public') IN ['public', 'followers']
+                        OR (visibility = 'friends' AND uid IN $close_to)
+                    )
+                    ORDER BY created_at DESC
+                    LIMIT $window
+            } ELSE {
+                []
+            };
+            LET $tags = SELECT VALUE out FROM hashtag_follows WHERE in = $uid;
+            LET $following = SELECT VALUE following_id FROM follows WHERE follower_id = $uid;
+            LET $tagged = IF array::len($tags) > 0 {
+                SELECT VALUE in FROM tagged
+                    WHERE out IN $tags
+                    AND (in.status ?? 'ready') = 'ready'
+                    AND (in.visibility ?? 'public') = 'public'
+                    AND in.archived != true
+                    AND (in.uid.is_private != true OR in.uid = $uid OR in.uid IN $following)
+                    ORDER BY created_at DESC
+                    LIMIT $window
+            } ELSE {
+                []
+            };
+            LET $ids = array::union(array::union($pushed, $pulled), $tagged);
+            SELECT * FROM $ids
+            ORDER BY created_at DESC
+            LIMIT $limit
+            START $offset;
+            ",
+        )
+        .bind(("uid", owner))
+        .bind(("threshold", FANOUT_FOLLOWER_LIMIT))
+        .bind(("window", offset.saturating_add(limit)))
+        .bind(("limit", limit))
+        .bind(("offset", offset))
+        .await?
+        .take(8)?;
+    Ok(posts)
+}
@@ -0,0 +1,18 @@
+use rocket::{Route, routes};
+
+use crate::stories::story_service::*;
+
+pub mod model;
+pub mod story_service;
+pub mod sweep_service;
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        post_story,
+        get_tray,
+        get_user_stories,
+        mark_seen,
+        get_viewers,
+        delete_story
+    ]
+}
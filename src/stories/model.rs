@@ -0,0 +1,101 @@
+use rocket::{FromForm, fs::TempFile};
+use serde::{Deserialize, Serialize};
+use surrealdb_types::{Datetime, RecordId, SurrealValue, ToSql};
+
+use crate::{
+    db::make_cursor,
+    posts::model::{AuthorSummary, AuthorSummaryResponse, Media, MediaResponse, PostStatus},
+};
+
+/// Hours a story stays visible after it is posted.
+pub const STORY_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, Serialize, Deserialize, SurrealValue)]
+pub struct Story {
+    pub id: RecordId,
+    pub uid: RecordId,
+    /// Always a single item; kept as a list so stories share the media
+    /// pipeline (and transcode queue) with posts.
+    pub media: Vec<Media>,
+    #[surreal(default)]
+    pub status: PostStatus,
+    pub views_count: usize,
+    pub created_at: Datetime,
+    pub expires_at: Datetime,
+}
+
+/// Multipart body for `/story`.
+#[derive(Debug, FromForm)]
+pub struct StoryFormRequest<'r> {
+    pub content: TempFile<'r>,
+    pub alt_text: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StoryResponse {
+    pub id: String,
+    pub uid: String,
+    pub media: Vec<MediaResponse>,
+    pub status: PostStatus,
+    /// Only reported to the story's owner.
+    pub views_count: Option<usize>,
+    pub created_at: Datetime,
+    pub expires_at: Datetime,
+    pub seen: bool,
+}
+
+impl From<Story> for StoryResponse {
+    fn from(story: Story) -> Self {
+        Self {
+            id: story.id.to_sql(),
+            uid: story.uid.to_sql(),
+            media: story.media.into_iter().map(Into::into).collect(),
+            status: story.status,
+            views_count: Some(story.views_count),
+            created_at: story.created_at,
+            expires_at: story.expires_at,
+            seen: false,
+        }
+    }
+}
+
+/// The minimum needed to build the tray.
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct TrayStory {
+    pub id: RecordId,
+    pub uid: RecordId,
+    pub created_at: Datetime,
+}
+
+/// One followed account in the story tray.
+#[derive(Debug, Serialize)]
+pub struct TrayEntryResponse {
+    pub author: AuthorSummaryResponse,
+    pub stories_count: usize,
+    pub unseen_count: usize,
+    pub latest_at: Datetime,
+}
+
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct StoryViewer {
+    pub user: AuthorSummary,
+    pub viewed_at: Datetime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StoryViewerResponse {
+    pub user: AuthorSummaryResponse,
+    pub viewed_at: Datetime,
+    /// Pass as `cursor` to continue after this viewer.
+    pub cursor: String,
+}
+
+impl From<StoryViewer> for StoryViewerResponse {
+    fn from(viewer: StoryViewer) -> Self {
+        Self {
+            cursor: make_cursor(&viewer.viewed_at, &viewer.user.id.to_sql()),
+            user: viewer.user.into(),
+            viewed_at: viewer.viewed_at,
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{Duration, Utc};
+use rocket::{State, delete, form::Form, get, post, put, serde::json::Json};
+use surrealdb_types::{Datetime, RecordId, ToSql};
+
+use crate::{
+    AppResult, DB, Store,
+    db::{parse_cursor, parse_thing, parse_thing_to_record},
+    error::AppError,
+    jwt::AuthUser,
+    media::transcode_service,
+    posts::{
+        model::{AuthorSummary, MediaKind, MediaResponse, PostStatus},
+        post_service,
+    },
+    stories::model::{
+        STORY_TTL_HOURS, Story, StoryFormRequest, StoryResponse, StoryViewer, StoryViewerResponse,
+        TrayEntryResponse, TrayStory,
+    },
+    users::user_service::is_blocked_between,
+};
+
+#[post("/story", data = "<form>", format = "multipart/form-data")]
+pub async fn post_story(
+    form: Form<StoryFormRequest<'_>>,
+    db: &State<DB>,
+    store: &State<Store>,
+    auth: AuthUser,
+) -> AppResult<Json<StoryResponse>> {
+    let store = store.inner().as_ref();
+    let uid = parse_thing(&auth.user_id)?;
+    let (_, owner) = parse_thing_to_record(&auth.user_id)?;
+    let alt_text = form
+        .alt_text
+        .as_deref()
+        .map(str::trim)
+        .filter(|alt| !alt.is_empty())
+        .map(str::to_string);
+    let media = post_service::save_media(
+        db,
+        store,
+        &format!("stories/{}", owner),
+        &form.content,
+        0,
+        alt_text,
+    )
+    .await?;
+    let status = if media.kind == MediaKind::Video {
+        PostStatus::Processing
+    } else {
+        PostStatus::Ready
+    };
+    let now = Utc::now();
+    let media = vec![media];
+    let created = db
+        .query(
+            "
+            CREATE ONLY stories SET
+                uid = $uid,
+                media = $media,
+                status = $status,
+                views_count = 0,
+                created_at = $created_at,
+                expires_at = $expires_at;
+            ",
+        )
+        .bind(("uid", uid))
+        .bind(("media", media.clone()))
+        .bind(("status", status))
+        .bind(("created_at", Datetime::from(now)))
+        .bind((
+            "expires_at",
+            Datetime::from(now + Duration::hours(STORY_TTL_HOURS)),
+        ))
+        .await
+        .and_then(|mut res| res.take::<Option<Story>>(0));
+    let story = match created {
+        Ok(Some(story)) => story,
+        Ok(None) => {
+            post_service::release_media(db, store, &media).await?;
+            return Err(AppError::XCustomMessage("Failed to post story"));
+        }
+        Err(e) => {
+            post_service::release_media(db, store, &media).await?;
+            return Err(e.into());
+        }
+    };
+    if story.status == PostStatus::Processing {
+        transcode_service::enqueue(db, &story.id, &story.media).await?;
+    }
+    Ok(Json(story.into()))
+}
+
+/// Followed accounts with live stories: accounts with unseen stories come
+/// first, then the most recently updated.
+#[get("/tray")]
+pub async fn get_tray(db: &State<DB>, auth: AuthUser) -> AppResult<Json<Vec<TrayEntryResponse>>> {
+    let uid = parse_thing(&auth.user_id)?;
+    let mut res = db
+        .query(
+            "
+            LET $following = SELECT VALUE following_id FROM follows WHERE follower_id = $uid;
+            LET $stories = SELECT id, uid, created_at FROM stories
+                WHERE uid IN $following
+                AND status = 'ready'
+                AND expires_at > time::now();
+            SELECT VALUE out FROM story_views WHERE in = $uid AND out IN $stories.id;
+            SELECT id, username, profile_picture, is_private FROM array::distinct($stories.uid);
+            RETURN $stories;
+            ",
+        )
+        .bind(("uid", uid))
+        .await?;
+    let seen: HashSet<String> = res
+        .take::<Vec<RecordId>>(2)?
+        .iter()
+        .map(|id| id.to_sql())
+        .collect();
+    let authors = res.take::<Vec<AuthorSummary>>(3)?;
+    let stories = res.take::<Vec<TrayStory>>(4)?;
+
+    // author -> (stories, unseen, latest)
+    let mut tallies: HashMap<String, (usize, usize, Datetime)> = HashMap::new();
+    for story in stories {
+        let unseen = usize::from(!seen.contains(&story.id.to_sql()));
+        tallies
+            .entry(story.uid.to_sql())
+            .and_modify(|(count, unseen_count, latest)| {
+                *count += 1;
+                *unseen_count += unseen;
+                if story.created_at > *latest {
+                    *latest = story.created_at;
+                }
+            })
+            .or_insert((1, unseen, story.created_at));
+    }
+    let mut tray: Vec<TrayEntryResponse> = authors
+        .into_iter()
+        .filter_map(|author| {
+            let (stories_count, unseen_count, latest_at) = tallies.remove(&author.id.to_sql())?;
+            Some(TrayEntryResponse {
+                author: author.into(),
+                stories_count,
+                unseen_count,
+                latest_at,
+            })
+        })
+        .collect();
+    tray.sort_by(|a, b| {
+        (b.unseen_count > 0)
+            .cmp(&(a.unseen_count > 0))
+            .then_with(|| b.latest_at.cmp(&a.latest_at))
+    });
+    Ok(Json(tray))
+}
+
+/// Live stories of one account, oldest first. Stories of private accounts
+/// are only shown to their followers, and none across a block.
+#[get("/user-stories/<user_id>")]
+pub async fn get_user_stories(
+    user_id: &str,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<StoryResponse>>> {
+    let viewer = parse_thing(&auth.user_id)?;
+    let author_id = parse_thing(user_id)?;
+    let is_owner = viewer == author_id;
+    let mut res = db
+        .query(
+            "
+            SELECT id, username, profile_picture, is_private FROM ONLY $author;
+            SELECT VALUE id FROM follows WHERE follower_id = $viewer AND following_id = $author;
+            SELECT * FROM stories
+                WHERE uid = $author
+                AND ($owner OR status = 'ready')
+                AND expires_at > time::now()
+                ORDER BY created_at ASC;
+            SELECT VALUE out FROM story_views
+                WHERE in = $viewer
+                AND out.uid = $author;
+            ",
+        )
+        .bind(("author", author_id))
+        .bind(("viewer", viewer.clone()))
+        .bind(("owner", is_owner))
+        .await?;
+    let author = res
+        .take::<Option<AuthorSummary>>(0)?
+        .ok_or(AppError::XCustomMessage("User not found"))?;
+    let follows = !res.take::<Vec<RecordId>>(1)?.is_empty();
+    if author.is_private && !is_owner && !follows {
+        return Err(AppError::Forbidden("This account is private"));
+    }
+    if !is_owner && is_blocked_between(db, &viewer, &author.id).await? {
+        return Err(AppError::XCustomMessage("User not found"));
+    }
+    let stories = res.take::<Vec<Story>>(2)?;
+    let seen: HashSet<String> = res
+        .take::<Vec<RecordId>>(3)?
+        .iter()
+        .map(|id| id.to_sql())
+        .collect();
+    Ok(Json(
+        stories
+            .into_iter()
+            .map(|story| {
+                let mut response: StoryResponse = story.into();
+                response.seen = seen.contains(&response.id);
+                if !is_owner {
+                    response.views_count = None;
+                }
+                if author.is_private {
+                    response.media.iter_mut().for_each(MediaResponse::sign);
+                }
+                response
+            })
+            .collect(),
+    ))
+}
+
+/// Records that the caller has seen a story they may see. Repeat views and
+/// the owner's own views are not counted.
+#[put("/seen/<id>")]
+pub async fn mark_seen(id: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let uid = parse_thing(&auth.user_id)?;
+    let story = find_story(db, parse_thing(id)?).await?;
+    if story.uid == uid {
+        return Ok("Own story".to_string());
+    }
+    ensure_visible(db, &uid, &story).await?;
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            IF (SELECT id FROM story_views WHERE in = $uid AND out = $sid) = [] {
+                RELATE $uid->story_views->$sid SET viewed_at = time::now();
+                UPDATE $sid SET views_count += 1;
+            };
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("uid", uid))
+        .bind(("sid", story.id))
+        .await?;
+    res.check()?;
+    Ok("Story seen".to_string())
+}
+
+#[get("/viewers/<id>?<cursor>&<limit>")]
+pub async fn get_viewers(
+    id: &str,
+    cursor: Option<String>,
+    limit: Option<u32>,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<StoryViewerResponse>>> {
+    let story = find_story(db, parse_thing(id)?).await?;
+    if story.uid != parse_thing(&auth.user_id)? {
+        return Err(AppError::Forbidden("Only the owner can see story viewers"));
+    }
+    let limit = limit.unwrap_or(20).min(100);
+    let (cursor_at, cursor_id) = cursor.as_deref().map(parse_cursor).transpose()?.unzip();
+    let viewers: Vec<StoryViewer> = db
+        .query(
+            "
+            SELECT
+                in.{ id, username, profile_picture } AS user,
+                viewed_at
+            FROM story_views
+            WHERE out = $sid
+            AND (
+                $cursor_at = NONE
+                OR viewed_at < $cursor_at
+                OR (viewed_at = $cursor_at AND in < $cursor_id)
+            )
+            ORDER BY viewed_at DESC, in DESC
+            LIMIT $limit
+            ",
+        )
+        .bind(("sid", story.id))
+        .bind(("cursor_at", cursor_at))
+        .bind(("cursor_id", cursor_id))
+        .bind(("limit", limit))
+        .await?
+        .take(0)?;
+    Ok(Json(viewers.into_iter().map(Into::into).collect()))
+}
+
+#[delete("/delete-story/<id>")]
+pub async fn delete_story(
+    id: &str,
+    db: &State<DB>,
+    store: &State<Store>,
+    auth: AuthUser,
+) -> AppResult<String> {
+    let story = find_story(db, parse_thing(id)?).await?;
+    if story.uid != parse_thing(&auth.user_id)? {
+        return Err(AppError::Forbidden("Only the owner can delete a story"));
+    }
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            DELETE story_views WHERE out = $sid;
+            DELETE transcode_jobs WHERE post = $sid;
+            DELETE $sid;
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("sid", story.id))
+        .await?;
+    res.check()?;
+    post_service::release_media(db, store.inner().as_ref(), &story.media).await?;
+    Ok("Story deleted".to_string())
+}
+
+/// Stories of private accounts are only visible to their followers, and no
+/// story is visible across a block.
+pub async fn ensure_visible(db: &DB, viewer: &RecordId, story: &Story) -> AppResult<()> {
+    if story.uid == *viewer {
+        return Ok(());
+    }
+    let mut res = db
+        .query(
+            "
+            SELECT VALUE is_private FROM ONLY $author;
+            SELECT VALUE id FROM follows WHERE follower_id = $viewer AND following_id = $author;
+            ",
+        )
+        .bind(("author", story.uid.clone()))
+        .bind(("viewer", viewer.clone()))
+        .await?;
+    let private = res.take::<Option<bool>>(0)?.unwrap_or(false);
+    let follows = !res.take::<Vec<RecordId>>(1)?.is_empty();
+    if private && !follows {
+        return Err(AppError::Forbidden("This account is private"));
+    }
+    if is_blocked_between(db, viewer, &story.uid).await? {
+        return Err(AppError::XCustomMessage("Story not found"));
+    }
+    Ok(())
+}
+
+async fn find_story(db: &DB, id: RecordId) -> AppResult<Story> {
+    db.query("SELECT * FROM ONLY $id WHERE expires_at > time::now()")
+        .bind(("id", id))
+        .await?
+        .take::<Option<Story>>(0)?
+        .ok_or(AppError::XCustomMessage("Story not found"))
+}
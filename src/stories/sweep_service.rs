@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use rocket::tokio;
+
+use crate::{AppResult, DB, Store, posts::post_service, stories::model::Story};
+
+/// How often expired stories are swept.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Runs the story sweeper forever.
+pub async fn run(db: DB, store: Store) {
+    loop {
+        if let Err(e) = sweep(&db, &store).await {
+            eprintln!("story sweep: {}", e);
+        }
+        tokio::time::sleep(SWEEP_INTERVAL).await;
+    }
+}
+
+/// Deletes expired stories together with their views and pending
+/// transcodes, then releases their media.
+async fn sweep(db: &DB, store: &Store) -> AppResult<()> {
+    let expired: Vec<Story> = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            LET $expired = SELECT VALUE id FROM stories WHERE expires_at <= time::now();
+            DELETE story_views WHERE out IN $expired;
+            DELETE transcode_jobs WHERE post IN $expired;
+            DELETE stories WHERE id IN $expired RETURN BEFORE;
+            COMMIT TRANSACTION;
+            ",
+        )
+        .await?
+        .take(3)?;
+    for story in expired {
+        post_service::release_media(db, store.as_ref(), &story.media).await?;
+    }
+    Ok(())
+}
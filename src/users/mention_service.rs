@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use surrealdb_types::RecordId;
+
+use crate::{
+    AppResult, DB,
+    users::model::{Mention, MentionTarget},
+};
+
+/// Only this many distinct accounts are linked per text.
+pub const MAX_MENTIONS_PER_TEXT: usize = 20;
+
+/// A `@name` occurrence before it is resolved against `users`.
+struct Candidate {
+    name: String,
+    offset: usize,
+    length: usize,
+}
+
+/// Finds the `@name` tokens in `text`. A mention starts with `@` at a word
+/// boundary (so e-mail addresses are skipped) and runs over letters, digits,
+/// `_` and `.`; a trailing `.` is treated as punctuation.
+fn extract_candidates(text: &str) -> Vec<Candidate> {
+    let chars: Vec<char> = text.chars().collect();
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    let mut candidates: Vec<Candidate> = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let at_boundary = i == 0 || !is_name_char(chars[i - 1]);
+        if chars[i] != '@' || !at_boundary {
+            i += 1;
+            continue;
+        }
+        let mut end = i + 1;
+        while end < chars.len() && is_name_char(chars[end]) {
+            end += 1;
+        }
+        let next = end;
+        while end > i + 1 && chars[end - 1] == '.' {
+            end -= 1;
+        }
+        if end > i + 1 {
+            let name: String = chars[i + 1..end].iter().collect::<String>().to_lowercase();
+            let known = names.contains(&name);
+            if known || names.len() < MAX_MENTIONS_PER_TEXT {
+                if !known {
+                    names.push(name.clone());
+                }
+                candidates.push(Candidate {
+                    name,
+                    offset: i,
+                    length: end - i,
+                });
+            }
+        }
+        i = next;
+    }
+    candidates
+}
+
+/// Resolves the mentions in `text` written by `author`. Names that match no
+/// account, or an account that has blocked or been blocked by the author,
+/// are left as plain text. Matching ignores case; an exact match wins when
+/// several accounts differ only in case.
+pub async fn resolve_mentions(db: &DB, author: &RecordId, text: &str) -> AppResult<Vec<Mention>> {
+    let candidates = extract_candidates(text);
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut names: Vec<String> = Vec::new();
+    for candidate in &candidates {
+        if !names.contains(&candidate.name) {
+            names.push(candidate.name.clone());
+        }
+    }
+    let targets: Vec<MentionTarget> = db
+        .query(
+            "
+            LET $blocked = array::union(
+                SELECT VALUE out FROM blocks WHERE in = $author,
+                SELECT VALUE in FROM blocks WHERE out = $author
+            );
+            SELECT id, username FROM users
+                WHERE string::lowercase(username) IN $names
+                AND id NOT IN $blocked;
+            ",
+        )
+        .bind(("author", author.clone()))
+        .bind(("names", names))
+        .await?
+        .take(1)?;
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut by_name: HashMap<String, Vec<MentionTarget>> = HashMap::new();
+    for target in targets {
+        by_name
+            .entry(target.username.to_lowercase())
+            .or_default()
+            .push(target);
+    }
+    Ok(candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let matches = by_name.get(&candidate.name)?;
+            let written: String = chars[candidate.offset + 1..candidate.offset + candidate.length]
+                .iter()
+                .collect();
+            let target = matches
+                .iter()
+                .find(|t| t.username == written)
+                .unwrap_or(&matches[0]);
+            Some(Mention {
+                user: target.id.clone(),
+                username: target.username.clone(),
+                offset: candidate.offset,
+                length: candidate.length,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(text: &str) -> Vec<(String, usize, usize)> {
+        extract_candidates(text)
+            .into_iter()
+            .map(|c| (c.name, c.offset, c.length))
+            .collect()
+    }
+
+    #[test]
+    fn offsets_count_characters() {
+        assert_eq!(
+            candidates("hi @Alice and 🎉 @bob_2"),
+            [("alice".to_string(), 3, 6), ("bob_2".to_string(), 16, 6)]
+        );
+    }
+
+    #[test]
+    fn email_addresses_are_not_mentions() {
+        assert!(candidates("mail a@b.com or me@example.org").is_empty());
+        assert_eq!(candidates("a@b.com @c"), [("c".to_string(), 8, 2)]);
+    }
+
+    #[test]
+    fn adjacent_punctuation_is_not_part_of_the_name() {
+        assert_eq!(
+            candidates("(@ann), @bo! @cy. @dee.jay... @ed's"),
+            [
+                ("ann".to_string(), 1, 4),
+                ("bo".to_string(), 8, 3),
+                ("cy".to_string(), 13, 3),
+                ("dee.jay".to_string(), 18, 8),
+                ("ed".to_string(), 30, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn bare_at_signs_are_skipped() {
+        assert_eq!(candidates("@ @. @_@"), [("_".to_string(), 5, 2)]);
+        assert_eq!(candidates("@@bob"), [("bob".to_string(), 1, 4)]);
+    }
+
+    #[test]
+    fn repeats_are_kept_but_distinct_names_are_capped() {
+        let text = (0..MAX_MENTIONS_PER_TEXT + 5)
+            .map(|i| format!("@u{}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(candidates(&text).len(), MAX_MENTIONS_PER_TEXT);
+        assert_eq!(candidates("@Sam @sam").len(), 2);
+    }
+}
@@ -1,7 +1,9 @@
 use rocket::{Route, routes};
 
+use crate::posts::post_service::get_user_grid;
 use crate::users::user_service::*;
 
+pub mod mention_service;
 pub mod model;
 pub mod user_service;
 
@@ -15,6 +17,17 @@ pub fn routes() -> Vec<Route> {
         follow_user,
         get_following_list,
         unfollow_user,
-        update_profile_picture
+        update_profile_picture,
+        update_privacy,
+        block_user,
+        unblock_user,
+        get_blocked_list,
+        mute_user,
+        unmute_user,
+        get_muted_list,
+        add_close_friend,
+        remove_close_friend,
+        get_close_friends,
+        get_user_grid
     ]
 }
@@ -6,17 +6,29 @@ use surrealdb::types::SurrealValue;
 use surrealdb::types::{RecordId, ToSql};
 use validator::Validate;
 
-use crate::AppResult;
+use crate::{
+    AppResult,
+    media::{
+        model::{Placeholder, Rendition, RenditionResponse},
+        urls,
+    },
+};
 
 #[derive(Debug, Serialize, Deserialize, SurrealValue)]
 pub struct User {
     pub id: RecordId,
     pub username: String,
     pub profile_picture: Option<String>,
+    #[surreal(default)]
+    pub profile_picture_renditions: Vec<Rendition>,
+    #[surreal(default)]
+    pub profile_picture_placeholder: Option<Placeholder>,
     pub email: String,
     pub mobile_number: String,
     pub followers_count: i64,
     pub following_count: i64,
+    #[surreal(default)]
+    pub is_private: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, SurrealValue)]
@@ -24,10 +36,16 @@ pub struct DBUser {
     pub id: RecordId,
     pub username: String,
     pub profile_picture: Option<String>,
+    #[surreal(default)]
+    pub profile_picture_renditions: Vec<Rendition>,
+    #[surreal(default)]
+    pub profile_picture_placeholder: Option<Placeholder>,
     pub email: String,
     pub mobile_number: String,
     pub followers_count: i64,
     pub following_count: i64,
+    #[surreal(default)]
+    pub is_private: bool,
     pub password_hash: String,
 }
 
@@ -36,9 +54,12 @@ pub struct UserResponse {
     pub id: String,
     pub username: String,
     pub profile_picture: Option<String>,
+    pub profile_picture_renditions: Vec<RenditionResponse>,
+    pub profile_picture_placeholder: Option<Placeholder>,
     pub email: String,
     pub followers_count: i64,
     pub following_count: i64,
+    pub is_private: bool,
     pub mobile_number: String,
 }
 
@@ -47,11 +68,18 @@ impl From<User> for UserResponse {
         Self {
             id: user.id.to_sql(),
             username: user.username,
-            profile_picture: user.profile_picture,
+            profile_picture: user.profile_picture.as_deref().map(urls::public_url),
+            profile_picture_renditions: user
+                .profile_picture_renditions
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            profile_picture_placeholder: user.profile_picture_placeholder,
             email: user.email,
             mobile_number: user.mobile_number,
             followers_count: user.followers_count,
             following_count: user.following_count,
+            is_private: user.is_private,
         }
     }
 }
@@ -110,3 +138,38 @@ pub struct Follow {
 pub struct Upload<'r> {
     pub file: TempFile<'r>,
 }
+
+/// A resolved `@username` in a caption or comment. `offset` and `length`
+/// count Unicode scalar values and cover the leading `@`.
+#[derive(Debug, Clone, Serialize, Deserialize, SurrealValue)]
+pub struct Mention {
+    pub user: RecordId,
+    pub username: String,
+    pub offset: usize,
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MentionResponse {
+    pub user: String,
+    pub username: String,
+    pub offset: usize,
+    pub length: usize,
+}
+
+impl From<Mention> for MentionResponse {
+    fn from(mention: Mention) -> Self {
+        Self {
+            user: mention.user.to_sql(),
+            username: mention.username,
+            offset: mention.offset,
+            length: mention.length,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, SurrealValue)]
+pub struct MentionTarget {
+    pub id: RecordId,
+    pub username: String,
+}
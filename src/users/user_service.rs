@@ -1,9 +1,10 @@
 use crate::{
-    AppResult, DB,
-    common_service::validate_image,
-    db::parse_thing,
+    AppResult, DB, Store,
+    db::{parse_thing, parse_thing_to_record},
     error::AppError,
     jwt::{AuthUser, generate_access_token, generate_refresh_token, refresh_access_token},
+    media::{image_service, model::Rendition, urls},
+    posts::timeline_service,
     users::model::{
         DBUser, Follow, LoginRequest, RefreshRequest, RegisterRequest, Upload, User, UserResponse,
     },
@@ -17,7 +18,6 @@ use serde_json::{Value, json};
 
 use surrealdb::types::ToSql;
 use surrealdb_types::RecordId;
-use uuid::Uuid;
 use validator::{Validate, ValidationError};
 
 #[post("/login", data = "<req>")]
@@ -28,7 +28,7 @@ pub async fn login_request(req: Json<LoginRequest>, db: &State<DB>) -> AppResult
 
 async fn login(db: &State<DB>, req: LoginRequest) -> AppResult<Value> {
     req.validate()?;
-    if req.email == None && req.username == None {
+    if req.email.is_none() && req.username.is_none() {
         return Err(AppError::ValidationError(ValidationError::new(
             "email or username required",
         )));
@@ -131,8 +131,15 @@ pub async fn get_follower_list(db: &State<DB>, auth: AuthUser) -> AppResult<Json
     Ok(Json(list))
 }
 
+/// Follows `uid`. The follow is attributed to the last of their posts the
+/// caller saw in the preceding day, for post insights.
 #[put("/follow-user/<uid>")]
 pub async fn follow_user(uid: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let myid = parse_thing(&auth.user_id)?;
+    let uid = parse_thing(uid)?;
+    if is_blocked_between(db, &myid, &uid).await? {
+        return Err(AppError::Forbidden("Cannot follow this user"));
+    }
     let mut res = db
         .query(
             "
@@ -140,18 +147,27 @@ pub async fn follow_user(uid: &str, db: &State<DB>, auth: AuthUser) -> AppResult
         CREATE follows SET
             follower_id = $myid,
             following_id = $uid,
+            source_post = (
+                SELECT post, created_at FROM post_views
+                WHERE viewer = $myid
+                AND author = $uid
+                AND created_at > time::now() - 1d
+                ORDER BY created_at DESC
+                LIMIT 1
+            )[0].post,
             created_at = time::now();
         UPDATE $myid SET following_count += 1;
         UPDATE $uid SET followers_count += 1;
         COMMIT TRANSACTION;
         ",
         )
-        .bind(("myid", parse_thing(&auth.user_id)?))
-        .bind(("uid", parse_thing(uid)?))
+        .bind(("myid", myid.clone()))
+        .bind(("uid", uid.clone()))
         .await?;
     let user: Follow = res
         .take::<Option<Follow>>(0)?
         .ok_or(AppError::XCustomMessage("Error occured"))?;
+    timeline_service::backfill(db, myid, uid).await?;
     Ok(format!("Followed user : {}", user.follower_id.to_sql()))
 }
 
@@ -174,6 +190,8 @@ pub async fn get_following_list(auth: AuthUser, db: &State<DB>) -> AppResult<Jso
 
 #[delete("/unfollow-user/<uid>")]
 pub async fn unfollow_user(uid: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let follower = parse_thing(&auth.user_id)?;
+    let following = parse_thing(uid)?;
     let mut res = db
         .query(
             "
@@ -188,13 +206,14 @@ pub async fn unfollow_user(uid: &str, db: &State<DB>, auth: AuthUser) -> AppResu
             COMMIT TRANSACTION;
         ",
         )
-        .bind(("follower", parse_thing(&auth.user_id)?))
-        .bind(("following", parse_thing(uid)?))
+        .bind(("follower", follower.clone()))
+        .bind(("following", following.clone()))
         .await?;
     let _follow: Follow = res
         .take::<Option<Follow>>(1)?
         .ok_or(AppError::XCustomMessage("Cannot Unfollow"))?;
-    Ok(format!("Unfollowed user"))
+    timeline_service::remove_author(db, follower, following).await?;
+    Ok("Unfollowed user".to_string())
 }
 
 #[post(
@@ -203,35 +222,229 @@ pub async fn unfollow_user(uid: &str, db: &State<DB>, auth: AuthUser) -> AppResu
     format = "multipart/form-data"
 )]
 pub async fn update_profile_picture(
-    mut upload: Form<Upload<'_>>,
+    upload: Form<Upload<'_>>,
     db: &State<DB>,
+    store: &State<Store>,
     auth: AuthUser,
 ) -> AppResult<String> {
-    let file = &mut upload.file;
+    let (_, owner) = parse_thing_to_record(&auth.user_id)?;
+    let image = image_service::store_image(
+        db,
+        store.inner().as_ref(),
+        &upload.file,
+        &format!("profile-pictures/{}", owner),
+    )
+    .await?;
+    let key = image.full().storage_key.clone();
+    let previous = db
+        .query(
+            "
+            UPDATE ONLY $id
+            SET profile_picture = $path,
+                profile_picture_renditions = $renditions,
+                profile_picture_placeholder = $placeholder
+            RETURN VALUE $before.profile_picture_renditions ?? []
+            ",
+        )
+        .bind(("id", parse_thing(&auth.user_id)?))
+        .bind(("path", key.clone()))
+        .bind(("renditions", image.renditions))
+        .bind(("placeholder", image.placeholder))
+        .await?
+        .take::<Option<Vec<Rendition>>>(0)?;
+    image_service::release(db, &previous.unwrap_or_default()).await?;
+    Ok(urls::public_url(&key))
+}
 
-    let filename = format!("{}.png", Uuid::new_v4());
-    let path = format!("data/profile-pictures/{}", filename);
-    let content_type =
-        file.content_type()
-            .ok_or(AppError::ValidationError(ValidationError::new(
-                "Missing content type",
-            )))?;
+#[put("/update-privacy?<private>")]
+pub async fn update_privacy(
+    private: bool,
+    db: &State<DB>,
+    auth: AuthUser,
+) -> AppResult<Json<UserResponse>> {
+    let user = db
+        .query("UPDATE $id SET is_private = $private RETURN AFTER")
+        .bind(("id", parse_thing(&auth.user_id)?))
+        .bind(("private", private))
+        .await?
+        .take::<Option<User>>(0)?
+        .ok_or(AppError::XCustomMessage("User not found"))?;
+    Ok(Json(user.into()))
+}
 
-    if !validate_image(content_type) {
+/// Blocks `uid`: follows and close friends entries in both directions are
+/// dropped, and neither side can follow or mention the other until the
+/// block is lifted.
+#[put("/block-user/<uid>")]
+pub async fn block_user(uid: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let myid = parse_thing(&auth.user_id)?;
+    let uid = parse_thing(uid)?;
+    if myid == uid {
         return Err(AppError::ValidationError(ValidationError::new(
-            "File must be an image",
+            "Cannot block yourself",
         )));
     }
-    file.persist_to(&path).await?;
-    let url = format!("http://localhost:8080/profile-pictures/{}", filename);
-    db.query(
-        "
-            UPDATE $id
-            SET profile_picture = $path
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            IF (SELECT id FROM blocks WHERE in = $myid AND out = $uid) = [] {
+                RELATE $myid->blocks->$uid SET created_at = time::now();
+            };
+            DELETE close_friends WHERE (in = $myid AND out = $uid) OR (in = $uid AND out = $myid);
+            FOR $pair IN [[$myid, $uid], [$uid, $myid]] {
+                LET $edge = SELECT VALUE id FROM follows
+                    WHERE follower_id = $pair[0] AND following_id = $pair[1];
+                IF $edge != [] {
+                    DELETE $edge;
+                    UPDATE $pair[0] SET following_count -= 1;
+                    UPDATE $pair[1] SET followers_count -= 1;
+                };
+            };
+            COMMIT TRANSACTION;
             ",
-    )
-    .bind(("id", parse_thing(&auth.user_id)?))
-    .bind(("path", url.clone()))
-    .await?;
-    Ok(url)
+        )
+        .bind(("myid", myid.clone()))
+        .bind(("uid", uid.clone()))
+        .await?;
+    res.check()?;
+    timeline_service::remove_author(db, myid.clone(), uid.clone()).await?;
+    timeline_service::remove_author(db, uid, myid).await?;
+    Ok("Blocked user".to_string())
+}
+
+#[delete("/unblock-user/<uid>")]
+pub async fn unblock_user(uid: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let res = db
+        .query("DELETE blocks WHERE in = $myid AND out = $uid")
+        .bind(("myid", parse_thing(&auth.user_id)?))
+        .bind(("uid", parse_thing(uid)?))
+        .await?;
+    res.check()?;
+    Ok("Unblocked user".to_string())
+}
+
+#[get("/get-blocked")]
+pub async fn get_blocked_list(auth: AuthUser, db: &State<DB>) -> AppResult<Json<Vec<String>>> {
+    let res = db
+        .query("SELECT VALUE out FROM blocks WHERE in = $id")
+        .bind(("id", parse_thing(&auth.user_id)?))
+        .await?
+        .take::<Vec<RecordId>>(0)?;
+    let list: Vec<String> = res.into_iter().map(|e| e.to_sql()).collect();
+    Ok(Json(list))
+}
+
+/// Mutes `uid`: their posts stay out of the caller's explore page, without
+/// unfollowing or telling them.
+#[put("/mute-user/<uid>")]
+pub async fn mute_user(uid: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let myid = parse_thing(&auth.user_id)?;
+    let uid = parse_thing(uid)?;
+    if myid == uid {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Cannot mute yourself",
+        )));
+    }
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            IF (SELECT id FROM mutes WHERE in = $myid AND out = $uid) = [] {
+                RELATE $myid->mutes->$uid SET created_at = time::now();
+            };
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("myid", myid))
+        .bind(("uid", uid))
+        .await?;
+    res.check()?;
+    Ok("Muted user".to_string())
+}
+
+#[delete("/unmute-user/<uid>")]
+pub async fn unmute_user(uid: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let res = db
+        .query("DELETE mutes WHERE in = $myid AND out = $uid")
+        .bind(("myid", parse_thing(&auth.user_id)?))
+        .bind(("uid", parse_thing(uid)?))
+        .await?;
+    res.check()?;
+    Ok("Unmuted user".to_string())
+}
+
+#[get("/get-muted")]
+pub async fn get_muted_list(auth: AuthUser, db: &State<DB>) -> AppResult<Json<Vec<String>>> {
+    let res = db
+        .query("SELECT VALUE out FROM mutes WHERE in = $id")
+        .bind(("id", parse_thing(&auth.user_id)?))
+        .await?
+        .take::<Vec<RecordId>>(0)?;
+    let list: Vec<String> = res.into_iter().map(|e| e.to_sql()).collect();
+    Ok(Json(list))
+}
+
+/// Adds `uid` to the caller's close friends, the audience of `friends`
+/// posts.
+#[put("/add-close-friend/<uid>")]
+pub async fn add_close_friend(uid: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let myid = parse_thing(&auth.user_id)?;
+    let uid = parse_thing(uid)?;
+    if myid == uid {
+        return Err(AppError::ValidationError(ValidationError::new(
+            "Cannot add yourself as a close friend",
+        )));
+    }
+    if is_blocked_between(db, &myid, &uid).await? {
+        return Err(AppError::Forbidden("Cannot add this user"));
+    }
+    let res = db
+        .query(
+            "
+            BEGIN TRANSACTION;
+            IF (SELECT id FROM close_friends WHERE in = $myid AND out = $uid) = [] {
+                RELATE $myid->close_friends->$uid SET created_at = time::now();
+            };
+            COMMIT TRANSACTION;
+            ",
+        )
+        .bind(("myid", myid))
+        .bind(("uid", uid))
+        .await?;
+    res.check()?;
+    Ok("Added to close friends".to_string())
+}
+
+#[delete("/remove-close-friend/<uid>")]
+pub async fn remove_close_friend(uid: &str, db: &State<DB>, auth: AuthUser) -> AppResult<String> {
+    let res = db
+        .query("DELETE close_friends WHERE in = $myid AND out = $uid")
+        .bind(("myid", parse_thing(&auth.user_id)?))
+        .bind(("uid", parse_thing(uid)?))
+        .await?;
+    res.check()?;
+    Ok("Removed from close friends".to_string())
+}
+
+#[get("/get-close-friends")]
+pub async fn get_close_friends(auth: AuthUser, db: &State<DB>) -> AppResult<Json<Vec<String>>> {
+    let res = db
+        .query("SELECT VALUE out FROM close_friends WHERE in = $id")
+        .bind(("id", parse_thing(&auth.user_id)?))
+        .await?
+        .take::<Vec<RecordId>>(0)?;
+    let list: Vec<String> = res.into_iter().map(|e| e.to_sql()).collect();
+    Ok(Json(list))
+}
+
+/// Whether either user has blocked the other.
+pub async fn is_blocked_between(db: &DB, a: &RecordId, b: &RecordId) -> AppResult<bool> {
+    let blocks: Vec<RecordId> = db
+        .query("SELECT VALUE id FROM blocks WHERE (in = $a AND out = $b) OR (in = $b AND out = $a)")
+        .bind(("a", a.clone()))
+        .bind(("b", b.clone()))
+        .await?
+        .take(0)?;
+    Ok(!blocks.is_empty())
 }
@@ -24,8 +24,7 @@ impl WsManager {
 
     pub fn send_to(&self, user: String, msg: String) -> AppResult<()> {
         if let Some(tx) = self.users.get(&user) {
-            let _ = tx
-                .send(msg)
+            tx.send(msg)
                 .map_err(|_| AppError::XCustomMessage("Failed to send message"))?;
         }
         Ok(())